@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PlayerId } from "./PlayerId";
+
+export type Event = { Counted: { by: PlayerId, count: number, } };
\ No newline at end of file
@@ -0,0 +1,10 @@
+// This file was generated by board-game-io-base. Do not edit this file manually.
+import type { ServerMessage } from "./ServerMessage";
+import type { ClientMessage } from "./ClientMessage";
+import type { View } from "./View";
+import type { Action } from "./Action";
+import type { Event } from "./Event";
+
+export type GameServerMessage = ServerMessage<View, Action, number, Event>;
+
+export type GameClientMessage = ClientMessage<Action, number>;
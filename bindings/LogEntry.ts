@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LogEvent } from "./LogEvent";
+
+export interface LogEntry { timestamp_ms: number, event: LogEvent, banks_ms: Array<[number, number]> | null, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PlayerResult } from "./PlayerResult";
+
+export interface Outcome { results: Array<PlayerResult>, }
\ No newline at end of file
@@ -2,4 +2,4 @@
 import type { PlayerId } from "./PlayerId";
 import type { UserId } from "./UserId";
 
-export interface UserInfo { id: UserId, username: string, leader: boolean, player_id: PlayerId | null, }
\ No newline at end of file
+export interface UserInfo { id: UserId, username: string, leader: boolean, player_id: PlayerId | null, connected: boolean, seat: number | null, active: boolean, muted: boolean, }
\ No newline at end of file
@@ -1,17 +1,27 @@
+use std::ops::RangeInclusive;
+
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use ts_rs::TS;
 
+use board_game_io_base::bindings::export_game_bindings;
 use board_game_io_base::error::Error;
-use board_game_io_base::game::Game;
+use board_game_io_base::game::{Game, GameRng};
 use board_game_io_base::ids::PlayerId;
 use board_game_io_base::result::Result;
 use board_game_io_base::server::Server;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, TS)]
 pub enum Action {
     Incr,
     Decr,
 }
 
+#[derive(Serialize, Clone, TS)]
+pub enum Event {
+    Counted { by: PlayerId, count: i32 },
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct MyGame {
     count: i32,
@@ -19,41 +29,87 @@ pub struct MyGame {
     players: Vec<PlayerId>,
 }
 
-#[derive(Serialize, Clone)]
-pub struct View<'a> {
-    count: &'a i32,
+#[derive(Serialize, Clone, TS)]
+pub struct View {
+    count: i32,
 }
 
 impl Game for MyGame {
-    type View<'a> = View<'a>;
+    type View<'a> = View;
     type Action = Action;
     type Config = u32;
+    type Event = Event;
+
+    const PUBLISH_LEGAL_ACTIONS: bool = true;
 
-    fn new(config: Self::Config, num_players: u32) -> Result<Self> {
+    fn new(config: Self::Config, num_players: u32, _rng: &mut GameRng) -> Result<Self> {
         Ok(MyGame {
             count: 0,
             max_value: config,
-            players: (0..num_players).map(|x| PlayerId(x)).collect(),
+            players: (0..num_players).map(PlayerId).collect(),
         })
     }
 
+    fn config_schema() -> Option<Value> {
+        Some(json!({
+            "title": "Max value",
+            "type": "integer",
+            "minimum": 0,
+            "maximum": 1000,
+        }))
+    }
+
+    fn validate_config(config: &Self::Config) -> Result<()> {
+        if *config > 1000 {
+            return Err(Error::InvalidConfig(
+                "max value is at most 1000".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn player_range(_config: &Self::Config) -> RangeInclusive<u32> {
+        1..=8
+    }
+
     fn players(&self) -> Vec<PlayerId> {
         self.players.clone()
     }
 
     fn view<'a>(&'a self, _player: Option<PlayerId>) -> Self::View<'a> {
-        View { count: &self.count }
+        View { count: self.count }
+    }
+
+    fn legal_actions(&self, _player: PlayerId) -> Vec<Self::Action> {
+        let mut actions = Vec::new();
+        if (self.count + 1).unsigned_abs() <= self.max_value {
+            actions.push(Action::Incr);
+        }
+        if (self.count - 1).unsigned_abs() <= self.max_value {
+            actions.push(Action::Decr);
+        }
+        actions
     }
 
-    fn do_action(&mut self, _player: PlayerId, action: &Self::Action) -> Result<()> {
+    fn do_action(
+        &mut self,
+        player: PlayerId,
+        action: &Self::Action,
+        _rng: &mut GameRng,
+        events: &mut Vec<Self::Event>,
+    ) -> Result<()> {
         let new_count = match *action {
             Self::Action::Incr => self.count + 1,
             Self::Action::Decr => self.count - 1,
         };
-        if new_count.abs() as u32 > self.max_value {
+        if new_count.unsigned_abs() > self.max_value {
             Err(Error::InvalidAction("count too high or low".to_string()))
         } else {
             self.count = new_count;
+            events.push(Event::Counted {
+                by: player,
+                count: new_count,
+            });
             Ok(())
         }
     }
@@ -61,5 +117,9 @@ impl Game for MyGame {
 
 #[tokio::main]
 async fn main() {
+    if std::env::args().any(|arg| arg == "--export-bindings") {
+        export_game_bindings::<MyGame>().expect("could not export bindings");
+        return;
+    }
     Server::<MyGame>::run("127.0.0.1:9002".to_string()).await;
 }
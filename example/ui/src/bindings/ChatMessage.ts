@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ChatChannel } from "./ChatChannel";
+import type { UserId } from "./UserId";
+
+export interface ChatMessage { id: number, from: UserId, username: string, text: string, channel: ChatChannel, timestamp_ms: number, }
\ No newline at end of file
@@ -1,5 +1,10 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ChatChannel } from "./ChatChannel";
+import type { PatchFormat } from "./PatchFormat";
 import type { ReconnectToken } from "./ReconnectToken";
 import type { RoomId } from "./RoomId";
+import type { SeatingPolicy } from "./SeatingPolicy";
+import type { UndoPolicy } from "./UndoPolicy";
+import type { UserId } from "./UserId";
 
-export type ClientMessage = { type: "join_room", username: string, room: RoomId | null, } | { type: "rejoin_room", token: ReconnectToken, room: RoomId, } | { type: "update_config", config: any, } | { type: "start_game" } | { type: "do_action", action: any, } | { type: "game_view_request" };
\ No newline at end of file
+export type ClientMessage<A, C> = { type: "create_room", username: string, } | { type: "join_room", username: string, room: RoomId, } | { type: "rejoin_room", token: ReconnectToken, room: RoomId, } | { type: "update_config", config: C, } | { type: "patch_config", patch: any, format: PatchFormat, } | { type: "kick_user", user: UserId, } | { type: "transfer_leadership", user: UserId, } | { type: "reassign_player", from_user: UserId, to_user: UserId, } | { type: "set_spectating", spectating: boolean, } | { type: "set_seat_order", users: Array<UserId>, } | { type: "set_seating_policy", policy: SeatingPolicy, } | { type: "set_undo_policy", policy: UndoPolicy, } | { type: "request_undo", actions: number, } | { type: "respond_undo", approve: boolean, } | { type: "cancel_undo" } | { type: "start_game" } | { type: "do_action", action: A, } | { type: "game_view_request" } | { type: "chat", text: string, channel: ChatChannel, } | { type: "mute_user", user: UserId, muted: boolean, } | { type: "game_log_request" } | { type: "replay_view_request", step: number, } | { type: "reset_to_lobby", reseat: boolean, } | { type: "rematch" };
\ No newline at end of file
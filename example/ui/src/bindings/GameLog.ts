@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LogEntry } from "./LogEntry";
+import type { PlayerId } from "./PlayerId";
+import type { UserId } from "./UserId";
+
+export interface GameLog { config: any, players: number, seed: number, seating_seed: number | null, player_mapping: Array<[UserId, PlayerId]>, entries: Array<LogEntry>, }
\ No newline at end of file
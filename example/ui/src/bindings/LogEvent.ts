@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PlayerId } from "./PlayerId";
+import type { UserId } from "./UserId";
+
+export type LogEvent = { type: "action", user_id: UserId, player_id: PlayerId, action: any, } | { type: "timeout", player_id: PlayerId, } | { type: "tick", now_ms: number, } | { type: "undo", from: number, };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PlayerId } from "./PlayerId";
+
+export interface PlayerClock { player: PlayerId, bank_ms: number | null, move_ms: number | null, running: boolean, }
\ No newline at end of file
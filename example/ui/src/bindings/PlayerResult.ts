@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PlayerId } from "./PlayerId";
+
+export interface PlayerResult { player: PlayerId, rank: number, score: number | null, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PlayerId } from "./PlayerId";
+import type { UserId } from "./UserId";
+
+export interface SeatingInfo { seed: number | null, players: Array<[UserId, PlayerId]>, }
\ No newline at end of file
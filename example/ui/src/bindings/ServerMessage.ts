@@ -1,7 +1,15 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ChatMessage } from "./ChatMessage";
+import type { GameLog } from "./GameLog";
+import type { Outcome } from "./Outcome";
+import type { PlayerClock } from "./PlayerClock";
 import type { ReconnectToken } from "./ReconnectToken";
 import type { RoomId } from "./RoomId";
+import type { SeatingInfo } from "./SeatingInfo";
+import type { SeatingPolicy } from "./SeatingPolicy";
+import type { UndoPolicy } from "./UndoPolicy";
+import type { UndoRequestInfo } from "./UndoRequestInfo";
 import type { UserId } from "./UserId";
 import type { UserInfo } from "./UserInfo";
 
-export type ServerMessage = { type: "error", message: string, } | { type: "join_response", room_id: RoomId, token: ReconnectToken, user_id: UserId, username: string, } | { type: "invalidate_token", token: ReconnectToken, } | { type: "user_info", users: Array<UserInfo>, } | { type: "room_info", config: any, } | { type: "game_info", view: any, } | { type: "game_view_diff", diff: any, } | { type: "invalid_action", message: string, };
\ No newline at end of file
+export type ServerMessage<V, A, C, E> = { type: "error", message: string, } | { type: "join_response", room_id: RoomId, token: ReconnectToken, user_id: UserId, username: string, } | { type: "invalidate_token", token: ReconnectToken, } | { type: "config_schema", schema: any, } | { type: "user_info", users: Array<UserInfo>, } | { type: "room_info", config: C, seating_policy: SeatingPolicy, undo_policy: UndoPolicy, can_start: boolean, start_error: string | null, } | { type: "game_info", view: V, version: number, seating: SeatingInfo | null, } | { type: "game_view_diff", diff: any, base_version: number, version: number, } | { type: "invalid_action", message: string, } | { type: "game_events", events: Array<E>, } | { type: "events_missed", missed: number, } | { type: "legal_actions", actions: Array<A>, } | { type: "game_over", outcome: Outcome | null, } | { type: "undo_request", request: UndoRequestInfo | null, } | { type: "game_log", log: GameLog, } | { type: "replay_view", step: number, view: V, } | { type: "chat", message: ChatMessage, } | { type: "chat_history", messages: Array<ChatMessage>, } | { type: "clock", players: Array<PlayerClock>, };
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { UserId } from "./UserId";
+
+export interface UndoRequestInfo { requester: UserId, actions: number, waiting_on: Array<UserId>, }
\ No newline at end of file
@@ -0,0 +1,57 @@
+use std::fs;
+use std::path::Path;
+
+use ts_rs::{ExportError, TS};
+
+use crate::game::Game;
+use crate::protocol::{ClientMessage, ServerMessage};
+
+type AnyServerMessage = ServerMessage<(), (), (), ()>;
+type AnyClientMessage = ClientMessage<(), ()>;
+
+fn import(name: &str) -> String {
+    format!("import type {{ {0} }} from \"./{0}\";\n", name)
+}
+
+// How GameMessages.ts refers to [T]. Types with their own declaration are exported next to it and
+// imported; built-in types such as numbers are written out by name.
+fn game_type<T: TS>(imports: &mut String) -> Result<String, ExportError> {
+    if T::EXPORT_TO.is_some() {
+        T::export()?;
+        imports.push_str(&import(&T::name()));
+    }
+    Ok(T::name())
+}
+
+// Exports the protocol bindings together with the game's view, action and config types, plus a
+// GameMessages.ts that names the protocol messages as used by this game, e.g.
+// `GameServerMessage = ServerMessage<View, Action, Config, Event>`. Files go next to ServerMessage.ts.
+pub fn export_game_bindings<T>() -> Result<(), ExportError>
+where
+    T: Game,
+    T::View<'static>: TS,
+    T::Action: TS,
+    T::Config: TS,
+    T::Event: TS,
+{
+    AnyServerMessage::export()?;
+    AnyClientMessage::export()?;
+    let mut imports = import(&AnyServerMessage::name()) + &import(&AnyClientMessage::name());
+    let view = game_type::<T::View<'static>>(&mut imports)?;
+    let action = game_type::<T::Action>(&mut imports)?;
+    let config = game_type::<T::Config>(&mut imports)?;
+    let event = game_type::<T::Event>(&mut imports)?;
+    let contents = format!(
+        "// This file was generated by board-game-io-base. Do not edit this file manually.\n{}\nexport type GameServerMessage = {};\n\nexport type GameClientMessage = {};\n",
+        imports,
+        AnyServerMessage::name_with_type_args(vec![view, action.clone(), config.clone(), event]),
+        AnyClientMessage::name_with_type_args(vec![action, config]),
+    );
+    let manifest_dir =
+        std::env::var("CARGO_MANIFEST_DIR").map_err(|_| ExportError::ManifestDirNotSet)?;
+    let path = Path::new(&manifest_dir)
+        .join(AnyServerMessage::EXPORT_TO.ok_or(ExportError::CannotBeExported)?)
+        .with_file_name("GameMessages.ts");
+    fs::write(path, contents)?;
+    Ok(())
+}
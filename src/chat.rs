@@ -0,0 +1,65 @@
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::ids::UserId;
+use crate::result::Result;
+
+#[derive(Serialize, Deserialize, TS, Clone, Debug, PartialEq)]
+#[ts(export)]
+pub struct ChatMessage {
+    // Increases with every message in the room
+    #[ts(type = "number")]
+    pub id: u64,
+    pub from: UserId,
+    pub username: String,
+    pub text: String,
+    pub channel: ChatChannel,
+    // Milliseconds since the Unix epoch
+    #[ts(type = "number")]
+    pub timestamp_ms: u64,
+}
+
+// Checks chat messages before they are sent. A filter may rewrite the text, e.g. to mask words, or
+// reject the message with an error that is shown to the sender.
+pub trait ChatFilter: Debug + Send + Sync + 'static {
+    fn filter(&self, from: UserId, text: String) -> Result<String>;
+}
+
+// Who a chat message is sent to.
+#[derive(Serialize, Deserialize, TS, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[ts(export)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatChannel {
+    // Everyone in the room. While a game is running, spectators' messages only reach other
+    // spectators.
+    #[default]
+    Room,
+    // Players in the current game, but not spectators
+    Players,
+    // Players on the sender's team, as reported by [Game::team]
+    Team,
+    // The sender and one other user
+    Whisper {
+        to: UserId,
+    },
+}
+
+// A chat message along with the users allowed to read it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChatEntry {
+    #[serde(flatten)]
+    pub message: ChatMessage,
+    // None means everyone in the room
+    pub recipients: Option<Vec<UserId>>,
+}
+
+impl ChatEntry {
+    pub fn visible_to(&self, user: &UserId) -> bool {
+        match &self.recipients {
+            Some(recipients) => recipients.contains(user),
+            None => true,
+        }
+    }
+}
@@ -1,20 +1,30 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 
 use futures_util::{SinkExt, StreamExt};
 use serde_json::Value;
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::tungstenite::{error::Error as TungsteniteError, Result};
 use tokio_tungstenite::WebSocketStream;
-use tracing::{span, Level};
+use tracing::{span, warn, Level};
 
 use crate::error::Error;
 use crate::game::Game;
 use crate::ids::*;
-use crate::protocol::{ClientMessage, ServerMessage};
+use crate::options::RoomOptions;
+use crate::protocol::{self, LobbyInfo, SeatingInfo};
 use crate::result::Result as MyResult;
-use crate::room_manager::{RoomManagerHandle, Subscription};
+use crate::room::Room;
+use crate::room_manager::{EventBatch, GameUpdate, RoomManagerHandle, Subscription};
+
+// The server handles game types as plain JSON.
+type ServerMessage = protocol::ServerMessage<Value, Value, Value, Value>;
+type ClientMessage = protocol::ClientMessage<Value, Value>;
+
+// How many random room ids to try before giving up on creating a room.
+const ROOM_ID_ATTEMPTS: usize = 100;
 
 pub struct ClientHandler<S: AsyncRead + AsyncWrite + Unpin, T: Game> {
     ws: WebSocketStream<S>,
@@ -22,6 +32,17 @@ pub struct ClientHandler<S: AsyncRead + AsyncWrite + Unpin, T: Game> {
     room_manager: RoomManagerHandle<T>,
     subscription: Subscription,
     last_view: Option<Value>,
+    // Version of [last_view] as sent to the client
+    view_version: u64,
+    // [GameUpdate::seq] of the last view sent
+    view_seq: u64,
+    // Events waiting for the view they lead to
+    pending_events: VecDeque<EventBatch>,
+    last_legal_actions: Option<Vec<Value>>,
+    // Sent along with every full view
+    seating: Option<SeatingInfo>,
+    // Batches of game events dropped since the client was last told
+    events_missed: u64,
 }
 
 async fn send<S: AsyncRead + AsyncWrite + Unpin>(
@@ -35,8 +56,31 @@ async fn send<S: AsyncRead + AsyncWrite + Unpin>(
 }
 
 impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
+    // Handler for a connection that has just joined a room.
+    fn joined(
+        ws: WebSocketStream<S>,
+        room_id: RoomId,
+        room_manager: RoomManagerHandle<T>,
+        subscription: Subscription,
+    ) -> Self {
+        Self {
+            ws,
+            room_id,
+            room_manager,
+            subscription,
+            last_view: None,
+            view_version: 0,
+            view_seq: 0,
+            pending_events: VecDeque::new(),
+            last_legal_actions: None,
+            seating: None,
+            events_missed: 0,
+        }
+    }
+
     pub async fn new(
         rooms: Arc<Mutex<HashMap<RoomId, RoomManagerHandle<T>>>>,
+        options: RoomOptions,
         mut ws: WebSocketStream<S>,
     ) -> Result<Self> {
         while let Some(msg) = ws.next().await {
@@ -44,27 +88,44 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
                 serde_json::from_str(msg?.to_text()?);
             if let Ok(client_message) = client_message {
                 match client_message {
-                    ClientMessage::JoinRoom { username, room } => {
-                        let room_id = match room {
-                            Some(room) => room,
-                            None => RoomId::new(),
+                    ClientMessage::CreateRoom { username } => {
+                        let result = match Self::create_room(&rooms, &options, RoomId::new) {
+                            Ok((room_id, room_manager)) => room_manager
+                                .join_room(username)
+                                .await
+                                .map(|subscription| (room_id, room_manager, subscription)),
+                            Err(err) => Err(err),
                         };
+                        match result {
+                            Ok((room_id, room_manager, subscription)) => {
+                                return Ok(Self::joined(ws, room_id, room_manager, subscription));
+                            }
+                            Err(err) => {
+                                send(
+                                    &mut ws,
+                                    &ServerMessage::Error {
+                                        message: err.to_string(),
+                                    },
+                                )
+                                .await?
+                            }
+                        }
+                    }
+                    ClientMessage::JoinRoom { username, room } => {
                         let room_manager = {
-                            let mut rooms = rooms.lock().unwrap();
-                            rooms
-                                .entry(room_id.clone())
-                                .or_insert_with(|| RoomManagerHandle::<T>::new())
-                                .clone()
+                            let rooms = rooms.lock().unwrap();
+                            rooms.get(&room).cloned()
+                        };
+                        let result = match room_manager {
+                            Some(room_manager) => room_manager
+                                .join_room(username)
+                                .await
+                                .map(|subscription| (room_manager, subscription)),
+                            None => Err(Error::RoomNotFound),
                         };
-                        match room_manager.join_room(username).await {
-                            Ok(subscription) => {
-                                return Ok(Self {
-                                    ws,
-                                    room_id,
-                                    room_manager,
-                                    subscription,
-                                    last_view: None,
-                                });
+                        match result {
+                            Ok((room_manager, subscription)) => {
+                                return Ok(Self::joined(ws, room, room_manager, subscription));
                             }
                             Err(err) => {
                                 send(
@@ -86,15 +147,14 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
                             Some(room_manager) => {
                                 match room_manager.rejoin_room(token.clone()).await {
                                     Ok(subscription) => {
-                                        return Ok(Self {
+                                        return Ok(Self::joined(
                                             ws,
-                                            room_id: room,
+                                            room,
                                             room_manager,
                                             subscription,
-                                            last_view: None,
-                                        });
+                                        ));
                                     }
-                                    Err(Error::InvalidReconnectToken) => {
+                                    Err(Error::InvalidReconnectToken | Error::RoomClosed) => {
                                         send(&mut ws, &ServerMessage::InvalidateToken { token })
                                             .await?
                                     }
@@ -137,6 +197,30 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
         Err(TungsteniteError::ConnectionClosed)
     }
 
+    // Creates a room under a fresh id from [new_id], retrying a limited number of times while the
+    // id is taken.
+    fn create_room(
+        rooms: &Arc<Mutex<HashMap<RoomId, RoomManagerHandle<T>>>>,
+        options: &RoomOptions,
+        mut new_id: impl FnMut() -> RoomId,
+    ) -> MyResult<(RoomId, RoomManagerHandle<T>)> {
+        let mut rooms_map = rooms.lock().unwrap();
+        let room_id = (0..ROOM_ID_ATTEMPTS)
+            .map(|_| new_id())
+            .find(|room_id| !rooms_map.contains_key(room_id))
+            .ok_or(Error::NoFreeRoomId)?;
+        let room_manager = {
+            let rooms = rooms.clone();
+            let room_id = room_id.clone();
+            let room = Room::new(options.clone());
+            RoomManagerHandle::<T>::new(room_id.clone(), room, move || {
+                rooms.lock().unwrap().remove(&room_id);
+            })
+        };
+        rooms_map.insert(room_id.clone(), room_manager.clone());
+        Ok((room_id, room_manager))
+    }
+
     async fn handle_result(&mut self, result: MyResult<()>) -> Result<()> {
         match result {
             Ok(()) => Ok(()),
@@ -159,26 +243,72 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
                     .update_config(self.subscription.user_id, config)
                     .await
             }
+            ClientMessage::PatchConfig { patch, format } => {
+                self.room_manager
+                    .patch_config(self.subscription.user_id, patch, format)
+                    .await
+            }
             ClientMessage::KickUser { user: target } => {
                 self.room_manager
                     .kick_user(self.subscription.user_id, target)
                     .await
             }
+            ClientMessage::TransferLeadership { user: target } => {
+                self.room_manager
+                    .transfer_leadership(self.subscription.user_id, target)
+                    .await
+            }
             ClientMessage::ReassignPlayer { from_user, to_user } => {
                 self.room_manager
                     .reassign_player(self.subscription.user_id, from_user, to_user)
                     .await
             }
+            ClientMessage::SetSpectating { spectating } => {
+                self.room_manager
+                    .set_spectating(self.subscription.user_id, spectating)
+                    .await
+            }
+            ClientMessage::SetSeatOrder { users } => {
+                self.room_manager
+                    .set_seat_order(self.subscription.user_id, users)
+                    .await
+            }
+            ClientMessage::SetSeatingPolicy { policy } => {
+                self.room_manager
+                    .set_seating_policy(self.subscription.user_id, policy)
+                    .await
+            }
+            ClientMessage::SetUndoPolicy { policy } => {
+                self.room_manager
+                    .set_undo_policy(self.subscription.user_id, policy)
+                    .await
+            }
+            ClientMessage::RequestUndo { actions } => {
+                self.room_manager
+                    .request_undo(self.subscription.user_id, actions)
+                    .await
+            }
+            ClientMessage::RespondUndo { approve } => {
+                self.room_manager
+                    .respond_undo(self.subscription.user_id, approve)
+                    .await
+            }
+            ClientMessage::CancelUndo => {
+                self.room_manager
+                    .cancel_undo(self.subscription.user_id)
+                    .await
+            }
             ClientMessage::StartGame => {
                 self.room_manager
                     .start_game(self.subscription.user_id)
                     .await
             }
-            ClientMessage::ResetToLobby => {
+            ClientMessage::ResetToLobby { reseat } => {
                 self.room_manager
-                    .reset_to_lobby(self.subscription.user_id)
+                    .reset_to_lobby(self.subscription.user_id, reseat)
                     .await
             }
+            ClientMessage::Rematch => self.room_manager.rematch(self.subscription.user_id).await,
             ClientMessage::DoAction { action } => {
                 self.room_manager
                     .do_action(self.subscription.user_id, action)
@@ -190,6 +320,8 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
                         &mut self.ws,
                         &ServerMessage::GameInfo {
                             view: last_view.clone(),
+                            version: self.view_version,
+                            seating: self.seating.clone(),
                         },
                     )
                     .await?;
@@ -197,6 +329,39 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
                 }
                 None => Ok(()),
             },
+            ClientMessage::Chat { text, channel } => {
+                self.room_manager
+                    .chat(self.subscription.user_id, text, channel)
+                    .await
+            }
+            ClientMessage::MuteUser {
+                user: target,
+                muted,
+            } => {
+                self.room_manager
+                    .mute_user(self.subscription.user_id, target, muted)
+                    .await
+            }
+            ClientMessage::GameLogRequest => match self.room_manager.game_log().await {
+                Ok(log) => {
+                    send(&mut self.ws, &ServerMessage::GameLog { log }).await?;
+                    Ok(())
+                }
+                Err(err) => Err(err),
+            },
+            ClientMessage::ReplayViewRequest { step } => {
+                match self
+                    .room_manager
+                    .replay_view(self.subscription.user_id, step)
+                    .await
+                {
+                    Ok(view) => {
+                        send(&mut self.ws, &ServerMessage::ReplayView { step, view }).await?;
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                }
+            }
             _ => {
                 send(
                     &mut self.ws,
@@ -211,6 +376,127 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
         self.handle_result(result).await
     }
 
+    // Sends [view] as a diff against the last view sent, or in full if there is none, [full] is set
+    // or the diff would be larger than the view itself.
+    async fn send_view(&mut self, view: Value, full: bool) -> Result<()> {
+        let base_version = self.view_version;
+        let message = match &self.last_view {
+            Some(last_view) if !full => {
+                let diff = json_patch::diff(last_view, &view);
+                if diff.0.is_empty() {
+                    None
+                } else {
+                    let diff = serde_json::to_value(diff).unwrap();
+                    if diff.to_string().len() > view.to_string().len() {
+                        Some(ServerMessage::GameInfo {
+                            view: view.clone(),
+                            version: base_version + 1,
+                            seating: self.seating.clone(),
+                        })
+                    } else {
+                        Some(ServerMessage::GameViewDiff {
+                            diff,
+                            base_version,
+                            version: base_version + 1,
+                        })
+                    }
+                }
+            }
+            _ => Some(ServerMessage::GameInfo {
+                view: view.clone(),
+                version: base_version + 1,
+                seating: self.seating.clone(),
+            }),
+        };
+        self.last_view = Some(view);
+        if let Some(message) = message {
+            self.view_version += 1;
+            send(&mut self.ws, &message).await?;
+        }
+        Ok(())
+    }
+
+    // Sends the pending events that come before the view numbered [view_seq].
+    async fn flush_events(&mut self, view_seq: u64) -> Result<()> {
+        while let Some(batch) = self.pending_events.front() {
+            if batch.view_seq.is_some_and(|seq| seq > view_seq) {
+                break;
+            }
+            let events = self.pending_events.pop_front().unwrap().events;
+            send(&mut self.ws, &ServerMessage::GameEvents { events }).await?;
+        }
+        Ok(())
+    }
+
+    async fn view_updated(&mut self, update: Option<GameUpdate>) -> Result<()> {
+        let _span = span!(Level::INFO, "view_updated");
+        match update {
+            Some(GameUpdate {
+                view,
+                legal_actions,
+                full,
+                seq,
+                seating,
+            }) => {
+                // A new seating means a new game, which the old view says nothing about
+                let full = full || seating != self.seating;
+                self.seating = seating;
+                // Events for this view were sent before it
+                self.receive_events();
+                self.flush_events(seq).await?;
+                self.view_seq = seq;
+                // A client that missed events can't trust a diff against what it built from them
+                let full = full || self.events_missed > 0;
+                self.report_missed_events().await?;
+                self.send_view(view, full).await?;
+                if let Some(actions) = legal_actions {
+                    if self.last_legal_actions.as_ref() != Some(&actions) {
+                        self.last_legal_actions = Some(actions.clone());
+                        send(&mut self.ws, &ServerMessage::LegalActions { actions }).await?;
+                    }
+                }
+            }
+            None => {
+                self.last_view = None;
+                self.last_legal_actions = None;
+                self.seating = None;
+                self.pending_events.clear();
+                self.events_missed = 0;
+            }
+        }
+        Ok(())
+    }
+
+    async fn report_missed_events(&mut self) -> Result<()> {
+        if self.events_missed > 0 {
+            let missed = std::mem::take(&mut self.events_missed);
+            send(&mut self.ws, &ServerMessage::EventsMissed { missed }).await?;
+        }
+        Ok(())
+    }
+
+    // Moves events that have already arrived into [pending_events].
+    fn receive_events(&mut self) {
+        loop {
+            match self.subscription.game_events.try_recv() {
+                Ok(batch) => self.pending_events.push_back(batch),
+                Err(TryRecvError::Lagged(missed)) => {
+                    warn!("connection missed {} batches of game events", missed);
+                    self.events_missed += missed;
+                }
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+    }
+
+    // Tells the room that this connection is gone.
+    pub async fn disconnect(&self) {
+        let _ = self
+            .room_manager
+            .disconnect(self.subscription.user_id)
+            .await;
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         send(
             &mut self.ws,
@@ -222,8 +508,16 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
             },
         )
         .await?;
+        if let Some(schema) = T::config_schema() {
+            send(&mut self.ws, &ServerMessage::ConfigSchema { schema }).await?;
+        }
+        let messages = std::mem::take(&mut self.subscription.chat_history);
+        send(&mut self.ws, &ServerMessage::ChatHistory { messages }).await?;
         let mut room_watch = self.room_manager.watch_room();
         let mut users_watch = self.room_manager.watch_users();
+        let mut outcome_watch = self.room_manager.watch_outcome();
+        let mut clock_watch = self.room_manager.watch_clock();
+        let mut undo_watch = self.room_manager.watch_undo();
         loop {
             tokio::select! {
                 message = self.ws.next() => {
@@ -238,38 +532,60 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
                 },
                 view_updated = self.subscription.game_view.changed() => {
                     if let Ok(()) = view_updated {
-                        let _span = span!(Level::INFO, "view_updated");
-                        let view = (*self.subscription.game_view.borrow()).clone();
-                        match view {
-                            Some(view) => {
-                                match &self.last_view {
-                                    None => {
-                                        self.last_view = Some(view.clone());
-                                        send(&mut self.ws, &ServerMessage::GameInfo { view: view }).await?;
-                                    },
-                                    Some(last_view) => {
-                                        // Send a diff instead
-                                        let diff = json_patch::diff(&last_view, &view);
-                                        if !diff.0.is_empty() {
-                                            send(&mut self.ws, &ServerMessage::GameViewDiff { diff: serde_json::to_value(diff).unwrap() }).await?;
-                                        }
-                                        self.last_view = Some(view);
-                                    },
-                                }
-                            },
-                            None => self.last_view = None,
-                        }
+                        let update = (*self.subscription.game_view.borrow()).clone();
+                        self.view_updated(update).await?;
+                    }
+                },
+                events = self.subscription.game_events.recv() => {
+                    match events {
+                        Ok(batch) => {
+                            self.pending_events.push_back(batch);
+                            self.flush_events(self.view_seq).await?;
+                        },
+                        Err(RecvError::Lagged(missed)) => {
+                            warn!("connection missed {} batches of game events", missed);
+                            self.events_missed += missed;
+                            // Resend the latest view in full rather than wait for the next change
+                            let update = self.subscription.game_view.borrow_and_update().clone();
+                            self.view_updated(update).await?;
+                        },
+                        Err(RecvError::Closed) => (),
+                    }
+                },
+                chat = self.subscription.chat.recv() => {
+                    match chat {
+                        Ok(message) => send(&mut self.ws, &ServerMessage::Chat { message }).await?,
+                        Err(RecvError::Lagged(missed)) => warn!("connection missed {} chat messages", missed),
+                        Err(RecvError::Closed) => (),
                     }
                 },
                 room_updated = room_watch.changed() => {
                     if let Ok(()) = room_updated {
-                        let config = (*room_watch.borrow()).clone();
-                        match config {
-                            Some(config) => send(&mut self.ws, &ServerMessage::RoomInfo { config }).await?,
-                            None => (),
+                        let lobby_info = (*room_watch.borrow()).clone();
+                        if let Some(LobbyInfo { config, seating_policy, undo_policy, start_error }) = lobby_info {
+                            let can_start = start_error.is_none();
+                            send(&mut self.ws, &ServerMessage::RoomInfo { config, seating_policy, undo_policy, can_start, start_error }).await?
                         }
                     }
                 },
+                outcome_updated = outcome_watch.changed() => {
+                    if let Ok(()) = outcome_updated {
+                        let outcome = (*outcome_watch.borrow()).clone();
+                        send(&mut self.ws, &ServerMessage::GameOver { outcome }).await?
+                    }
+                },
+                undo_updated = undo_watch.changed() => {
+                    if let Ok(()) = undo_updated {
+                        let request = (*undo_watch.borrow()).clone();
+                        send(&mut self.ws, &ServerMessage::UndoRequest { request }).await?
+                    }
+                },
+                clock_updated = clock_watch.changed() => {
+                    if let Ok(()) = clock_updated {
+                        let players = (*clock_watch.borrow()).clone().unwrap_or_default();
+                        send(&mut self.ws, &ServerMessage::Clock { players }).await?
+                    }
+                },
                 users_updated = users_watch.changed() => {
                     if let Ok(()) = users_updated {
                         let users = (*users_watch.borrow()).clone();
@@ -286,3 +602,208 @@ impl<S: AsyncRead + AsyncWrite + Unpin, T: Game> ClientHandler<S, T> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::io::{duplex, DuplexStream};
+    use tokio::sync::broadcast;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+
+    use super::*;
+    use crate::test_game::TestGame;
+
+    type Rooms = Arc<Mutex<HashMap<RoomId, RoomManagerHandle<TestGame>>>>;
+
+    // The server and client ends of a websocket.
+    async fn websocket() -> (WebSocketStream<DuplexStream>, WebSocketStream<DuplexStream>) {
+        let (server, client) = duplex(1 << 16);
+        (
+            WebSocketStream::from_raw_socket(server, Role::Server, None).await,
+            WebSocketStream::from_raw_socket(client, Role::Client, None).await,
+        )
+    }
+
+    // A handler whose websocket leads to the returned client end, with game events coming from the
+    // returned sender rather than a game.
+    async fn handler() -> (
+        ClientHandler<DuplexStream, TestGame>,
+        WebSocketStream<DuplexStream>,
+        broadcast::Sender<EventBatch>,
+    ) {
+        let (server, client) = websocket().await;
+        let room_id = RoomId::new();
+        let room_manager =
+            RoomManagerHandle::new(room_id.clone(), Room::new(RoomOptions::default()), || ());
+        let mut subscription = room_manager.join_room("a".to_string()).await.unwrap();
+        let (events_tx, game_events) = broadcast::channel(16);
+        subscription.game_events = game_events;
+        let handler = ClientHandler::joined(server, room_id, room_manager, subscription);
+        (handler, client, events_tx)
+    }
+
+    fn update(view: Value, seq: u64) -> Option<GameUpdate> {
+        Some(GameUpdate {
+            view,
+            legal_actions: None,
+            full: false,
+            seq,
+            seating: None,
+        })
+    }
+
+    async fn receive(client: &mut WebSocketStream<DuplexStream>) -> ServerMessage {
+        let message = client.next().await.unwrap().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn new_rooms_skip_taken_ids() {
+        let rooms = Rooms::default();
+        let taken = RoomId::from("AAAA".to_string());
+        ClientHandler::<DuplexStream, TestGame>::create_room(
+            &rooms,
+            &RoomOptions::default(),
+            || taken.clone(),
+        )
+        .unwrap();
+        assert!(rooms.lock().unwrap().contains_key(&taken));
+
+        let mut ids = ["AAAA", "AAAA", "BBBB"].into_iter();
+        let (room_id, _) = ClientHandler::<DuplexStream, TestGame>::create_room(
+            &rooms,
+            &RoomOptions::default(),
+            || RoomId::from(ids.next().unwrap().to_string()),
+        )
+        .unwrap();
+        assert_eq!(room_id, RoomId::from("BBBB".to_string()));
+
+        // Every attempt hits the taken id
+        assert!(matches!(
+            ClientHandler::<DuplexStream, TestGame>::create_room(
+                &rooms,
+                &RoomOptions::default(),
+                || taken.clone()
+            ),
+            Err(Error::NoFreeRoomId)
+        ));
+        assert_eq!(rooms.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn joining_a_missing_room_fails() {
+        let (server, mut client) = websocket().await;
+        let connecting = tokio::spawn(ClientHandler::<_, TestGame>::new(
+            Rooms::default(),
+            RoomOptions::default(),
+            server,
+        ));
+        let join = ClientMessage::JoinRoom {
+            username: "a".to_string(),
+            room: RoomId::from("ABCD".to_string()),
+        };
+        client
+            .send(Message::text(serde_json::to_string(&join).unwrap()))
+            .await
+            .unwrap();
+        let ServerMessage::Error { message } = receive(&mut client).await else {
+            panic!("joining should fail");
+        };
+        assert_eq!(message, Error::RoomNotFound.to_string());
+        connecting.abort();
+    }
+
+    #[tokio::test]
+    async fn diffs_chain_onto_the_previous_version() {
+        let (mut handler, mut client, _) = handler().await;
+        let mut view = json!({ "total": 0, "history": ["a long entry that stays the same"] });
+        handler.view_updated(update(view.clone(), 1)).await.unwrap();
+        let ServerMessage::GameInfo { version: 1, .. } = receive(&mut client).await else {
+            panic!("the first view should be sent in full");
+        };
+
+        // Versions count the views sent on this connection, so they go up by one even when the
+        // room's refreshes skip ahead
+        let mut expected_version = 1;
+        for (seq, total) in [(2, 3), (3, 5), (7, 8)] {
+            let mut expected = view.clone();
+            view["total"] = json!(total);
+            handler
+                .view_updated(update(view.clone(), seq))
+                .await
+                .unwrap();
+            let ServerMessage::GameViewDiff {
+                diff,
+                base_version,
+                version,
+            } = receive(&mut client).await
+            else {
+                panic!("small changes should be sent as diffs");
+            };
+            assert_eq!(base_version, expected_version);
+            expected_version += 1;
+            assert_eq!(version, expected_version);
+            let diff: json_patch::Patch = serde_json::from_value(diff).unwrap();
+            json_patch::patch(&mut expected, &diff).unwrap();
+            assert_eq!(expected, view);
+        }
+    }
+
+    #[tokio::test]
+    async fn views_are_sent_in_full_when_the_diff_is_larger() {
+        let (mut handler, mut client, _) = handler().await;
+        let first = json!({ "a": 1, "b": 2, "c": 3 });
+        handler.view_updated(update(first, 1)).await.unwrap();
+        receive(&mut client).await;
+
+        let second = json!({ "d": 4 });
+        handler
+            .view_updated(update(second.clone(), 2))
+            .await
+            .unwrap();
+        let ServerMessage::GameInfo { view, version, .. } = receive(&mut client).await else {
+            panic!("the view should be sent in full");
+        };
+        assert_eq!(view, second);
+        assert_eq!(version, 2);
+    }
+
+    #[tokio::test]
+    async fn events_arrive_before_their_view() {
+        let (mut handler, mut client, events_tx) = handler().await;
+        for view_seq in [1, 2] {
+            events_tx
+                .send(EventBatch {
+                    view_seq: Some(view_seq),
+                    events: vec![json!(view_seq)],
+                })
+                .unwrap();
+        }
+        handler
+            .view_updated(update(json!({ "total": 1 }), 1))
+            .await
+            .unwrap();
+        let ServerMessage::GameEvents { events } = receive(&mut client).await else {
+            panic!("events should come first");
+        };
+        assert_eq!(events, vec![json!(1)]);
+        let ServerMessage::GameInfo { version: 1, .. } = receive(&mut client).await else {
+            panic!("the view should follow its events");
+        };
+
+        // The events for the next view wait for it
+        handler
+            .view_updated(update(json!({ "total": 2 }), 2))
+            .await
+            .unwrap();
+        let ServerMessage::GameEvents { events } = receive(&mut client).await else {
+            panic!("events should come first");
+        };
+        assert_eq!(events, vec![json!(2)]);
+        assert!(matches!(
+            receive(&mut client).await,
+            ServerMessage::GameInfo { version: 2, .. }
+                | ServerMessage::GameViewDiff { version: 2, .. }
+        ));
+    }
+}
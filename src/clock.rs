@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::time::Instant;
+use ts_rs::TS;
+
+use crate::ids::PlayerId;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TimeControl {
+    // Time allowed for a single move
+    pub per_move: Option<Duration>,
+    // Total time each player has for the whole game
+    pub bank: Option<Duration>,
+    // Time added to a player's bank after each of their moves
+    pub increment: Duration,
+}
+
+#[derive(Serialize, Deserialize, TS, Clone, Debug, PartialEq)]
+#[ts(export)]
+pub struct PlayerClock {
+    pub player: PlayerId,
+    // Remaining time in the player's bank, in milliseconds
+    #[ts(type = "number | null")]
+    pub bank_ms: Option<u64>,
+    // Remaining time for the current move, in milliseconds
+    #[ts(type = "number | null")]
+    pub move_ms: Option<u64>,
+    pub running: bool,
+}
+
+// Chess-clock style timers for the players of a single game.
+pub struct Clock {
+    control: TimeControl,
+    banks: HashMap<PlayerId, Duration>,
+    // Players whose time is currently counting down
+    running: Vec<PlayerId>,
+    turn_started: Instant,
+    // Running players whose time already ran out this turn
+    timed_out: Vec<PlayerId>,
+}
+
+// How a clock is kept in room snapshots. The time already used this turn is kept, so the turn
+// carries on from where it was when the clock is loaded again.
+#[derive(Serialize, Deserialize)]
+struct SavedClock {
+    control: TimeControl,
+    banks_ms: Vec<(PlayerId, u64)>,
+    running: Vec<PlayerId>,
+    turn_elapsed_ms: u64,
+    timed_out: Vec<PlayerId>,
+}
+
+impl Serialize for Clock {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut banks_ms: Vec<(PlayerId, u64)> = self
+            .banks
+            .iter()
+            .map(|(player, bank)| (*player, bank.as_millis() as u64))
+            .collect();
+        banks_ms.sort();
+        SavedClock {
+            control: self.control.clone(),
+            banks_ms,
+            running: self.running.clone(),
+            turn_elapsed_ms: self.turn_started.elapsed().as_millis() as u64,
+            timed_out: self.timed_out.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Clock {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = SavedClock::deserialize(deserializer)?;
+        let now = Instant::now();
+        Ok(Self {
+            control: saved.control,
+            banks: saved
+                .banks_ms
+                .into_iter()
+                .map(|(player, ms)| (player, Duration::from_millis(ms)))
+                .collect(),
+            running: saved.running,
+            turn_started: now
+                .checked_sub(Duration::from_millis(saved.turn_elapsed_ms))
+                .unwrap_or(now),
+            timed_out: saved.timed_out,
+        })
+    }
+}
+
+impl Clock {
+    pub fn new(
+        control: TimeControl,
+        players: &[PlayerId],
+        running: Vec<PlayerId>,
+        now: Instant,
+    ) -> Self {
+        let bank = control.bank.unwrap_or_default();
+        Self {
+            banks: players.iter().map(|player| (*player, bank)).collect(),
+            control,
+            running,
+            turn_started: now,
+            timed_out: Vec::new(),
+        }
+    }
+
+    pub fn running(&self) -> &Vec<PlayerId> {
+        &self.running
+    }
+
+    // Time left in each player's bank at [now], or None if there are no banks.
+    pub fn banks(&self, now: Instant) -> Option<Vec<(PlayerId, Duration)>> {
+        self.control.bank?;
+        let mut banks: Vec<(PlayerId, Duration)> = self
+            .banks
+            .keys()
+            .map(|player| (*player, self.remaining(player, now).0.unwrap_or_default()))
+            .collect();
+        banks.sort();
+        Some(banks)
+    }
+
+    // Sets the banks back to [banks], as returned by [banks], and starts a new turn for [running].
+    pub fn restore(
+        &mut self,
+        banks: &[(PlayerId, Duration)],
+        running: Vec<PlayerId>,
+        now: Instant,
+    ) {
+        self.banks.extend(banks.iter().copied());
+        self.running = running;
+        self.turn_started = now;
+        self.timed_out.clear();
+    }
+
+    // Charges the time since the turn started to every running player and starts a new turn.
+    // [acted] is the player that made a move, if any, and receives the increment.
+    pub fn update(&mut self, acted: Option<PlayerId>, running: Vec<PlayerId>, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.turn_started);
+        for player in self.running.iter() {
+            if let Some(bank) = self.banks.get_mut(player) {
+                *bank = bank.saturating_sub(elapsed);
+            }
+        }
+        if let Some(bank) = acted.and_then(|player| self.banks.get_mut(&player)) {
+            *bank += self.control.increment;
+        }
+        self.running = running;
+        self.turn_started = now;
+        self.timed_out.clear();
+    }
+
+    fn remaining(&self, player: &PlayerId, now: Instant) -> (Option<Duration>, Option<Duration>) {
+        let elapsed = if self.running.contains(player) {
+            now.saturating_duration_since(self.turn_started)
+        } else {
+            Duration::ZERO
+        };
+        let bank = self.control.bank.map(|_| {
+            self.banks
+                .get(player)
+                .copied()
+                .unwrap_or_default()
+                .saturating_sub(elapsed)
+        });
+        let per_move = self
+            .control
+            .per_move
+            .map(|per_move| per_move.saturating_sub(elapsed));
+        (bank, per_move)
+    }
+
+    fn deadline(&self, player: &PlayerId) -> Option<Instant> {
+        let (bank, per_move) = self.remaining(player, self.turn_started);
+        let limit = [bank, per_move].into_iter().flatten().min()?;
+        Some(self.turn_started + limit)
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.running
+            .iter()
+            .filter(|player| !self.timed_out.contains(player))
+            .filter_map(|player| self.deadline(player))
+            .min()
+    }
+
+    // Running players whose time has run out. Each player is reported once per turn.
+    pub fn expired(&mut self, now: Instant) -> Vec<PlayerId> {
+        let expired: Vec<PlayerId> = self
+            .running
+            .iter()
+            .filter(|player| !self.timed_out.contains(player))
+            .filter(|player| matches!(self.deadline(player), Some(deadline) if deadline <= now))
+            .copied()
+            .collect();
+        self.timed_out.extend(expired.iter());
+        expired
+    }
+
+    pub fn info(&self, now: Instant) -> Vec<PlayerClock> {
+        let mut players: Vec<PlayerId> = self.banks.keys().copied().collect();
+        players.sort();
+        players
+            .into_iter()
+            .map(|player| {
+                let (bank, per_move) = self.remaining(&player, now);
+                PlayerClock {
+                    player,
+                    bank_ms: bank.map(|bank| bank.as_millis() as u64),
+                    move_ms: per_move.map(|per_move| per_move.as_millis() as u64),
+                    running: self.running.contains(&player),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: PlayerId = PlayerId(0);
+    const B: PlayerId = PlayerId(1);
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn bank_ms(clock: &Clock, player: PlayerId, now: Instant) -> Option<u64> {
+        clock
+            .info(now)
+            .into_iter()
+            .find(|info| info.player == player)
+            .unwrap()
+            .bank_ms
+    }
+
+    #[test]
+    fn bank_runs_only_for_running_players() {
+        let start = Instant::now();
+        let control = TimeControl {
+            bank: Some(secs(60)),
+            ..TimeControl::default()
+        };
+        let mut clock = Clock::new(control, &[A, B], vec![A], start);
+        assert_eq!(bank_ms(&clock, A, start + secs(10)), Some(50_000));
+        assert_eq!(bank_ms(&clock, B, start + secs(10)), Some(60_000));
+        clock.update(Some(A), vec![B], start + secs(10));
+        assert_eq!(bank_ms(&clock, A, start + secs(30)), Some(50_000));
+        assert_eq!(bank_ms(&clock, B, start + secs(30)), Some(40_000));
+    }
+
+    #[test]
+    fn increment_goes_to_the_player_that_acted() {
+        let start = Instant::now();
+        let control = TimeControl {
+            bank: Some(secs(60)),
+            increment: secs(5),
+            ..TimeControl::default()
+        };
+        let mut clock = Clock::new(control, &[A, B], vec![A], start);
+        clock.update(Some(A), vec![B], start + secs(10));
+        assert_eq!(bank_ms(&clock, A, start + secs(10)), Some(55_000));
+        // Turns that end without a move earn nothing
+        clock.update(None, vec![A], start + secs(20));
+        assert_eq!(bank_ms(&clock, B, start + secs(20)), Some(50_000));
+    }
+
+    #[test]
+    fn per_move_limit_resets_every_turn() {
+        let start = Instant::now();
+        let control = TimeControl {
+            per_move: Some(secs(10)),
+            ..TimeControl::default()
+        };
+        let mut clock = Clock::new(control, &[A, B], vec![A], start);
+        assert_eq!(clock.next_deadline(), Some(start + secs(10)));
+        clock.update(Some(A), vec![B], start + secs(8));
+        assert_eq!(clock.next_deadline(), Some(start + secs(18)));
+        let info = clock.info(start + secs(9));
+        assert_eq!(info[1].move_ms, Some(9_000));
+        assert_eq!(info[0].bank_ms, None);
+    }
+
+    #[test]
+    fn deadline_is_the_earlier_of_bank_and_per_move() {
+        let start = Instant::now();
+        let control = TimeControl {
+            per_move: Some(secs(30)),
+            bank: Some(secs(20)),
+            ..TimeControl::default()
+        };
+        let clock = Clock::new(control, &[A], vec![A], start);
+        assert_eq!(clock.next_deadline(), Some(start + secs(20)));
+    }
+
+    #[test]
+    fn expiry_is_reported_once_per_turn() {
+        let start = Instant::now();
+        let control = TimeControl {
+            bank: Some(secs(10)),
+            ..TimeControl::default()
+        };
+        let mut clock = Clock::new(control, &[A, B], vec![A], start);
+        assert!(clock.expired(start + secs(5)).is_empty());
+        assert_eq!(clock.expired(start + secs(10)), vec![A]);
+        assert!(clock.expired(start + secs(11)).is_empty());
+        assert_eq!(clock.next_deadline(), None);
+        assert_eq!(bank_ms(&clock, A, start + secs(11)), Some(0));
+    }
+
+    #[test]
+    fn restore_puts_banks_back() {
+        let start = Instant::now();
+        let control = TimeControl {
+            bank: Some(secs(60)),
+            ..TimeControl::default()
+        };
+        let mut clock = Clock::new(control, &[A, B], vec![A], start);
+        let banks = clock.banks(start + secs(10)).unwrap();
+        assert_eq!(banks, vec![(A, secs(50)), (B, secs(60))]);
+        clock.update(Some(A), vec![B], start + secs(10));
+        clock.update(Some(B), vec![A], start + secs(40));
+        clock.restore(&banks, vec![A], start + secs(40));
+        assert_eq!(bank_ms(&clock, A, start + secs(40)), Some(50_000));
+        assert_eq!(bank_ms(&clock, B, start + secs(40)), Some(60_000));
+    }
+}
@@ -24,18 +24,70 @@ pub enum Error {
     GameAlreadyStarted,
     #[error("game has not started")]
     GameNotStarted,
+    #[error("game is over")]
+    GameFinished,
+    #[error("game is not over yet")]
+    GameNotFinished,
+    #[error("seat order can only be changed when seating is leader-defined")]
+    SeatOrderNotEditable,
+    #[error("seat order must contain every seated user exactly once")]
+    InvalidSeatOrder,
     #[error("invalid player mapping")]
     InvalidPlayerMapping,
+    #[error("could not apply patch: {0}")]
+    InvalidPatch(String),
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("game needs between {0} and {1} players")]
+    PlayerCountOutOfRange(u32, u32),
     #[error("game state has wrong number of players")]
     WrongPlayerCount,
     #[error("could not create game state")]
     InvalidCreate,
+    #[error("it is not your turn")]
+    NotYourTurn,
     #[error("user is spectating")]
     UserNotInGame,
+    #[error("undo is disabled in this room")]
+    UndoDisabled,
+    #[error("there is nothing to undo")]
+    NothingToUndo,
+    #[error("an undo request is already pending")]
+    UndoPending,
+    #[error("there is no pending undo request")]
+    NoUndoRequest,
+    #[error("user cannot approve this undo request")]
+    NotUndoApprover,
+    #[error("only the user who asked for the undo can cancel it")]
+    NotUndoRequester,
+    #[error("you are muted")]
+    Muted,
+    #[error("message is empty")]
+    EmptyMessage,
+    #[error("message is longer than {0} characters")]
+    MessageTooLong(usize),
+    #[error("message rejected: {0}")]
+    MessageRejected(String),
+    #[error("you are not on a team")]
+    NoTeam,
+    #[error("players and spectators cannot whisper to each other during a game")]
+    WhisperAcrossSeats,
+    #[error("you cannot whisper to yourself")]
+    WhisperToSelf,
+    #[error("no game to replay")]
+    NoGameLog,
+    #[error("replay step is out of range")]
+    InvalidReplayStep,
     #[error("invalid action: {0}")]
     InvalidAction(String),
-    #[error("encountered tokio error: {0}")]
-    TokioError(String),
+    #[error("no free room id could be found")]
+    NoFreeRoomId,
+    #[error("room not found")]
+    RoomNotFound,
+    #[error("room is closed")]
+    RoomClosed,
+    #[error("storage error: {0}")]
+    StorageError(String),
     #[error("unknown error")]
     Unknown,
 }
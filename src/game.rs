@@ -1,15 +1,200 @@
-use serde::{de::DeserializeOwned, Serialize};
+use std::ops::RangeInclusive;
+use std::time::SystemTime;
 
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::clock::TimeControl;
 use crate::ids::PlayerId;
 use crate::result::Result;
 
+// Randomness handed to games. Games should draw from it rather than from their own generator, so
+// that replays and tests see exactly the same values.
+pub type GameRng = ChaCha8Rng;
+
+// The generator for the [step]th change to a game, where step 0 is [Game::new]. Each change gets its
+// own stream so the values drawn do not depend on how much earlier changes consumed.
+pub fn game_rng(seed: u64, step: u64) -> GameRng {
+    let mut rng = GameRng::seed_from_u64(seed);
+    rng.set_stream(step);
+    rng
+}
+
+// A fresh seed for games or seating. Seeds are sent to clients as JSON numbers, so they are kept
+// below 2^53 where JavaScript can still hold them exactly.
+pub(crate) fn random_seed() -> u64 {
+    rand::thread_rng().gen_range(0..1 << 53)
+}
+
+// JSON Schema for a type deriving [schemars::JsonSchema], for use in [Game::config_schema].
+#[cfg(feature = "schema")]
+pub fn json_schema<C: schemars::JsonSchema>() -> Value {
+    serde_json::to_value(schemars::schema_for!(C)).unwrap()
+}
+
+// Final standing of a single player. Ranks start at 1, and players that tie share a rank.
+#[derive(Serialize, Deserialize, TS, Clone, Debug, PartialEq)]
+#[ts(export)]
+pub struct PlayerResult {
+    pub player: PlayerId,
+    pub rank: u32,
+    #[ts(type = "number | null")]
+    pub score: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, TS, Clone, Debug, PartialEq)]
+#[ts(export)]
+pub struct Outcome {
+    pub results: Vec<PlayerResult>,
+}
+
+impl Outcome {
+    // Players in earlier groups rank higher; players in the same group are tied.
+    pub fn ranked(groups: Vec<Vec<PlayerId>>) -> Self {
+        let mut results = Vec::new();
+        let mut rank = 1;
+        for group in groups {
+            let size = group.len() as u32;
+            results.extend(group.into_iter().map(|player| PlayerResult {
+                player,
+                rank,
+                score: None,
+            }));
+            rank += size;
+        }
+        Self { results }
+    }
+
+    pub fn winners(winners: &[PlayerId], players: &[PlayerId]) -> Self {
+        let losers = players
+            .iter()
+            .filter(|player| !winners.contains(player))
+            .copied()
+            .collect();
+        Self::ranked(vec![winners.to_vec(), losers])
+    }
+
+    pub fn draw(players: &[PlayerId]) -> Self {
+        Self::ranked(vec![players.to_vec()])
+    }
+
+    pub fn with_score(mut self, player: PlayerId, score: i64) -> Self {
+        if let Some(result) = self.results.iter_mut().find(|r| r.player == player) {
+            result.score = Some(score);
+        }
+        self
+    }
+}
+
 pub trait Game: Serialize + Send + Sync + Sized + Clone + 'static {
     type View<'a>: Serialize;
     type Action: Serialize + DeserializeOwned;
     type Config: Default + Clone + Send + Sync + Serialize + DeserializeOwned;
+    // Something that happened in the game, e.g. a card being drawn, for clients to animate or log.
+    type Event: Serialize + Clone + Send + Sync;
+
+    // Whether [legal_actions] should be sent to players along with their view.
+    const PUBLISH_LEGAL_ACTIONS: bool = false;
 
-    fn new(_: Self::Config, players: u32) -> Result<Self>;
+    fn new(_: Self::Config, players: u32, rng: &mut GameRng) -> Result<Self>;
     fn players(&self) -> Vec<PlayerId>;
     fn view<'a>(&'a self, _: Option<PlayerId>) -> Self::View<'a>;
-    fn do_action(&mut self, _: PlayerId, _: &Self::Action) -> Result<()>;
+    // Events pushed to [events] are sent to clients along with the resulting views.
+    fn do_action(
+        &mut self,
+        _: PlayerId,
+        _: &Self::Action,
+        rng: &mut GameRng,
+        events: &mut Vec<Self::Event>,
+    ) -> Result<()>;
+
+    // JSON Schema describing [Config], sent to clients when they join so that a generic lobby can
+    // render and check a settings form. Titles, enums and ranges in the schema are shown as-is.
+    fn config_schema() -> Option<Value> {
+        None
+    }
+
+    // Called whenever the leader changes the config; an error rejects the change. Games should
+    // report bad values with [Error::InvalidConfig].
+    fn validate_config(_: &Self::Config) -> Result<()> {
+        Ok(())
+    }
+
+    // How many players a game with this config can be started with.
+    fn player_range(_: &Self::Config) -> RangeInclusive<u32> {
+        1..=u32::MAX
+    }
+
+    // Actions the player could currently take. Only used when [PUBLISH_LEGAL_ACTIONS] is set.
+    fn legal_actions(&self, _: PlayerId) -> Vec<Self::Action> {
+        Vec::new()
+    }
+
+    // Players who may currently act. None means anyone may act at any time; otherwise actions
+    // from other players are rejected without calling [do_action].
+    fn active_players(&self) -> Option<Vec<PlayerId>> {
+        None
+    }
+
+    // Timers to run for a game created with this config. Only players returned by
+    // [active_players] have their time running.
+    fn time_control(_: &Self::Config) -> Option<TimeControl> {
+        None
+    }
+
+    // Called when a running player's time runs out. The game may pass, play a default action or
+    // end the game through [outcome]. Each timeout is reported once per turn.
+    fn on_timeout(
+        &mut self,
+        _: PlayerId,
+        _rng: &mut GameRng,
+        _events: &mut Vec<Self::Event>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    // When the game next wants [tick] to be called without any player acting. Wall-clock time is
+    // used so that it can be stored in the game state.
+    fn next_wakeup(&self) -> Option<SystemTime> {
+        None
+    }
+
+    // Called once [next_wakeup] has passed, with [now] rounded down to whole milliseconds as kept in
+    // the game log. Must move [next_wakeup] forward or clear it, otherwise it will be called again
+    // straight away.
+    fn tick(
+        &mut self,
+        _now: SystemTime,
+        _rng: &mut GameRng,
+        _events: &mut Vec<Self::Event>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    // What [viewer] gets to see of [event], called with the state after the change that emitted it.
+    // None hides the event entirely.
+    fn event_view(&self, event: &Self::Event, _viewer: Option<PlayerId>) -> Option<Self::Event> {
+        Some(event.clone())
+    }
+
+    // Called after every change to the state: the viewers, as passed to [view], whose views may have
+    // changed. Views that are not listed are not recomputed. None means every view. Legal actions
+    // are recomputed for every player regardless.
+    fn changed_views(&self) -> Option<Vec<Option<PlayerId>>> {
+        None
+    }
+
+    // The team [player] is on, for team chat. Players on the same team share a number; None means
+    // the player has no teammates to chat with.
+    fn team(&self, _: PlayerId) -> Option<u32> {
+        None
+    }
+
+    // Called after every successful action. Returning Some ends the game.
+    fn outcome(&self) -> Option<Outcome> {
+        None
+    }
 }
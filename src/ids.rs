@@ -28,6 +28,18 @@ impl fmt::Display for UserId {
     }
 }
 
+impl fmt::Display for RoomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<String> for RoomId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
 impl ReconnectToken {
     pub fn new() -> Self {
         Self(
@@ -40,14 +52,25 @@ impl ReconnectToken {
     }
 }
 
+impl Default for ReconnectToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RoomId {
     pub fn new() -> Self {
         Self(
             rand::thread_rng()
                 .sample_iter(&Uniform::new_inclusive('A', 'Z'))
                 .take(4)
-                .map(char::from)
                 .collect(),
         )
     }
 }
+
+impl Default for RoomId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,12 +1,20 @@
+pub mod bindings;
+pub mod chat;
 pub mod client_handler;
+pub mod clock;
 pub mod error;
 pub mod game;
 pub mod ids;
+pub mod options;
 pub mod protocol;
+pub mod replay;
 pub mod result;
 pub mod room;
 pub mod room_manager;
 pub mod server;
+pub mod storage;
+#[cfg(test)]
+mod test_game;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::chat::ChatFilter;
+use crate::storage::Storage;
+
+#[derive(Clone, Debug)]
+pub struct RoomOptions {
+    // How long a user without any open connection stays in the room before being dropped.
+    // Users holding a seat in the current game are never dropped.
+    pub disconnect_grace: Duration,
+    // How long the leader may be disconnected before leadership passes to the next connected user.
+    pub leader_timeout: Duration,
+    // How long a room may go without any connected user before it is shut down and forgotten.
+    pub idle_ttl: Duration,
+    // Where room snapshots are kept across restarts, if anywhere.
+    pub storage: Option<Arc<dyn Storage>>,
+    // How long changes are held back before a snapshot is written, so that a burst of changes is
+    // saved once.
+    pub save_delay: Duration,
+    // How many chat messages are kept for users who join later.
+    pub chat_history: usize,
+    // Longest chat message allowed, in characters.
+    pub chat_max_length: usize,
+    // Run on every chat message before it is sent, if set.
+    pub chat_filter: Option<Arc<dyn ChatFilter>>,
+    // Seed for every game's randomness. Games get a fresh random seed when this is None. Clients
+    // only see seeds exactly if they are below 2^53.
+    pub game_seed: Option<u64>,
+}
+
+impl Default for RoomOptions {
+    fn default() -> Self {
+        Self {
+            disconnect_grace: Duration::from_secs(60),
+            leader_timeout: Duration::from_secs(30),
+            idle_ttl: Duration::from_secs(30 * 60),
+            storage: None,
+            save_delay: Duration::from_secs(1),
+            chat_history: 100,
+            chat_max_length: 500,
+            chat_filter: None,
+            game_seed: None,
+        }
+    }
+}
@@ -2,7 +2,11 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use ts_rs::TS;
 
+use crate::chat::{ChatChannel, ChatMessage};
+use crate::clock::PlayerClock;
+use crate::game::Outcome;
 use crate::ids::*;
+use crate::replay::GameLog;
 
 #[derive(Serialize, Deserialize, TS, Clone, Debug)]
 #[ts(export)]
@@ -11,24 +15,91 @@ pub struct UserInfo {
     pub username: String,
     pub leader: bool,
     pub player_id: Option<PlayerId>,
+    // False while the user has no open connection to the room
+    pub connected: bool,
+    // Position in the lobby seat order, or None if the user is spectating
+    pub seat: Option<u32>,
+    // True if it is this user's turn in the current game
+    pub active: bool,
+    // Muted users cannot chat
+    pub muted: bool,
 }
 
-impl UserInfo {
-    pub fn new(id: UserId, username: String, leader: bool, player_id: Option<PlayerId>) -> Self {
-        Self {
-            id,
-            username,
-            leader,
-            player_id,
-        }
-    }
+// How seated users are matched with players when the game starts.
+#[derive(Serialize, Deserialize, TS, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum SeatingPolicy {
+    // Seats follow the order in which users first sat down
+    JoinOrder,
+    // Seats are shuffled when the game starts
+    Random,
+    // Seats follow the order set by the leader
+    #[default]
+    LeaderDefined,
+}
+
+// How the patch in [ClientMessage::PatchConfig] is written.
+#[derive(Serialize, Deserialize, TS, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum PatchFormat {
+    // RFC 7386 JSON merge patch
+    #[default]
+    Merge,
+    // RFC 6902 JSON patch
+    Json,
+}
+
+// Who has to agree before an undo request is carried out.
+#[derive(Serialize, Deserialize, TS, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[ts(export)]
+#[serde(rename_all = "snake_case")]
+pub enum UndoPolicy {
+    Disabled,
+    // The leader approves, or undoes immediately when they ask
+    #[default]
+    Leader,
+    // Every other player in the game approves
+    Consensus,
+}
+
+// How the users of the current game were matched with its players.
+#[derive(Serialize, Deserialize, TS, Clone, Debug, PartialEq, Eq)]
+#[ts(export)]
+pub struct SeatingInfo {
+    // Seed the seats were shuffled with, if seating was random
+    #[ts(type = "number | null")]
+    pub seed: Option<u64>,
+    // In the order the game lists its players
+    pub players: Vec<(UserId, PlayerId)>,
+}
+
+#[derive(Serialize, Deserialize, TS, Clone, Debug, PartialEq, Eq)]
+#[ts(export)]
+pub struct UndoRequestInfo {
+    pub requester: UserId,
+    pub actions: u32,
+    // Users whose approval is still missing
+    pub waiting_on: Vec<UserId>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LobbyInfo {
+    pub config: Value,
+    pub seating_policy: SeatingPolicy,
+    pub undo_policy: UndoPolicy,
+    // Why the game cannot be started yet, if it cannot
+    pub start_error: Option<String>,
 }
 
-// Message from the server to the client.
+// Message from the server to the client. [V], [A], [C] and [E] are the game's view, action, config
+// and event types; the server sends them as plain JSON, and they only exist to type the TypeScript
+// bindings.
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export)]
 #[serde(tag = "type", rename_all = "snake_case")]
-pub enum ServerMessage {
+pub enum ServerMessage<V, A, C, E> {
     Error {
         message: String,
     },
@@ -41,56 +112,167 @@ pub enum ServerMessage {
     InvalidateToken {
         token: ReconnectToken,
     },
+    // Sent after joining if the game describes its config
+    ConfigSchema {
+        #[ts(type = "any")]
+        schema: Value,
+    },
     UserInfo {
         users: Vec<UserInfo>,
     },
     RoomInfo {
-        #[ts(type = "any")]
-        config: Value,
+        config: C,
+        seating_policy: SeatingPolicy,
+        undo_policy: UndoPolicy,
+        can_start: bool,
+        // Why the game cannot be started yet, if it cannot
+        start_error: Option<String>,
     },
+    // Views are numbered per connection. A full view may be sent at any time; a client that sees a
+    // diff whose [base_version] is not the version it holds should send a GameViewRequest.
     GameInfo {
-        #[ts(type = "any")]
-        view: Value,
+        view: V,
+        #[ts(type = "number")]
+        version: u64,
+        seating: Option<SeatingInfo>,
     },
     GameViewDiff {
         #[ts(type = "any")]
         diff: Value,
+        #[ts(type = "number")]
+        base_version: u64,
+        #[ts(type = "number")]
+        version: u64,
     },
     InvalidAction {
         message: String,
     },
+    // Events from a change to the game, sent just before the view update it caused
+    GameEvents {
+        events: Vec<E>,
+    },
+    // The connection fell behind and [missed] batches of game events were dropped. The view is
+    // sent again in full right after.
+    EventsMissed {
+        #[ts(type = "number")]
+        missed: u64,
+    },
+    LegalActions {
+        actions: Vec<A>,
+    },
+    // Sent with the outcome when the game ends, and with None once a new game replaces it
+    GameOver {
+        outcome: Option<Outcome>,
+    },
+    // Sent whenever an undo request is made or resolved
+    UndoRequest {
+        request: Option<UndoRequestInfo>,
+    },
+    GameLog {
+        log: GameLog,
+    },
+    ReplayView {
+        step: u32,
+        view: V,
+    },
+    Chat {
+        message: ChatMessage,
+    },
+    // Recent chat, sent after joining
+    ChatHistory {
+        messages: Vec<ChatMessage>,
+    },
+    // Sent whenever clocks start, stop or are adjusted; clients count down locally in between. An
+    // empty list means no clocks are running.
+    Clock {
+        players: Vec<PlayerClock>,
+    },
 }
 
-// Message from the client to the server.
+// Message from the client to the server. As with [ServerMessage], [A] and [C] are the game's action
+// and config types.
 #[derive(Serialize, Deserialize, TS)]
 #[ts(export)]
 #[serde(tag = "type", rename_all = "snake_case")]
-pub enum ClientMessage {
+pub enum ClientMessage<A, C> {
+    CreateRoom {
+        username: String,
+    },
     JoinRoom {
         username: String,
-        // None to create a new room.
-        room: Option<RoomId>,
+        room: RoomId,
     },
     RejoinRoom {
         token: ReconnectToken,
         room: RoomId,
     },
     UpdateConfig {
+        config: C,
+    },
+    // Change part of the config, applied to the server's current config
+    PatchConfig {
         #[ts(type = "any")]
-        config: Value,
+        patch: Value,
+        #[serde(default)]
+        format: PatchFormat,
     },
     KickUser {
         user: UserId,
     },
+    TransferLeadership {
+        user: UserId,
+    },
     ReassignPlayer {
         from_user: UserId,
         to_user: UserId,
     },
+    SetSpectating {
+        spectating: bool,
+    },
+    SetSeatOrder {
+        users: Vec<UserId>,
+    },
+    SetSeatingPolicy {
+        policy: SeatingPolicy,
+    },
+    SetUndoPolicy {
+        policy: UndoPolicy,
+    },
+    // Take back the last [actions] actions
+    RequestUndo {
+        actions: u32,
+    },
+    RespondUndo {
+        approve: bool,
+    },
+    // Withdraw your own undo request
+    CancelUndo,
     StartGame,
     DoAction {
-        #[ts(type = "any")]
-        action: Value,
+        action: A,
     },
     GameViewRequest,
-    ResetToLobby,
+    Chat {
+        text: String,
+        #[serde(default)]
+        channel: ChatChannel,
+    },
+    MuteUser {
+        user: UserId,
+        muted: bool,
+    },
+    // Log of the most recent game, once it is over
+    GameLogRequest,
+    // View of the most recent game after [step] log entries, once it is over
+    ReplayViewRequest {
+        step: u32,
+    },
+    // Return to the lobby, keeping the config. [reseat] seats exactly the players of the last game,
+    // in their playing order, and everyone else spectates.
+    ResetToLobby {
+        #[serde(default)]
+        reseat: bool,
+    },
+    // Once the game is over, start again with the same players and a different first player
+    Rematch,
 }
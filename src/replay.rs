@@ -0,0 +1,160 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::error::Error;
+use crate::game::{game_rng, Game, GameRng};
+use crate::ids::{PlayerId, UserId};
+use crate::result::Result;
+
+pub(crate) fn timestamp_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[derive(Serialize, Deserialize, TS, Clone, Debug)]
+#[ts(export)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogEvent {
+    Action {
+        user_id: UserId,
+        player_id: PlayerId,
+        #[ts(type = "any")]
+        action: Value,
+    },
+    Timeout {
+        player_id: PlayerId,
+    },
+    // The game advanced on its own at [now_ms], in milliseconds since the Unix epoch.
+    Tick {
+        #[ts(type = "number")]
+        now_ms: u64,
+    },
+    // The entries from index [from] on that were still in effect were taken back. They stay in the
+    // log but no longer count.
+    Undo {
+        from: usize,
+    },
+}
+
+#[derive(Serialize, Deserialize, TS, Clone, Debug)]
+#[ts(export)]
+pub struct LogEntry {
+    // Milliseconds since the Unix epoch
+    #[ts(type = "number")]
+    pub timestamp_ms: u64,
+    pub event: LogEvent,
+    // Time left in each player's clock bank just before this entry, in milliseconds, so that undo
+    // can give it back
+    #[ts(type = "Array<[number, number]> | null")]
+    pub banks_ms: Option<Vec<(PlayerId, u64)>>,
+}
+
+// Everything needed to rebuild a game from scratch: the arguments to [Game::new] and every
+// change made to the state afterwards, in order.
+#[derive(Serialize, Deserialize, TS, Clone, Debug)]
+#[ts(export)]
+pub struct GameLog {
+    #[ts(type = "any")]
+    pub config: Value,
+    pub players: u32,
+    // Seed for the game's randomness. Clients only get the log once the game is over, since the
+    // seeds give away everything the game hides.
+    #[ts(type = "number")]
+    pub seed: u64,
+    #[ts(type = "number | null")]
+    pub seating_seed: Option<u64>,
+    pub player_mapping: Vec<(UserId, PlayerId)>,
+    pub entries: Vec<LogEntry>,
+}
+
+impl GameLog {
+    pub fn new<T: Game>(
+        config: &T::Config,
+        players: u32,
+        seed: u64,
+        seating_seed: Option<u64>,
+        player_mapping: Vec<(UserId, PlayerId)>,
+    ) -> Self {
+        Self {
+            config: serde_json::to_value(config).unwrap(),
+            players,
+            seed,
+            seating_seed,
+            player_mapping,
+            entries: Vec::new(),
+        }
+    }
+
+    // The generator for the next change to the game. Streams follow the changes still in effect, so
+    // that a change made again after an undo draws the same values.
+    pub fn next_rng(&self) -> GameRng {
+        let changes = self.live_entries(self.entries.len()).len();
+        game_rng(self.seed, changes as u64 + 1)
+    }
+
+    // The changes among the first [steps] entries that were not taken back by an undo within those
+    // steps, along with their indices.
+    pub fn live_entries(&self, steps: usize) -> Vec<(usize, &LogEntry)> {
+        let mut live = Vec::new();
+        for (index, entry) in self.entries.iter().take(steps).enumerate() {
+            match entry.event {
+                LogEvent::Undo { from } => live.retain(|(index, _)| *index < from),
+                _ => live.push((index, entry)),
+            }
+        }
+        live
+    }
+
+    pub fn push(
+        &mut self,
+        time: SystemTime,
+        event: LogEvent,
+        banks: Option<Vec<(PlayerId, Duration)>>,
+    ) {
+        self.entries.push(LogEntry {
+            timestamp_ms: timestamp_ms(time),
+            event,
+            banks_ms: banks.map(|banks| {
+                banks
+                    .into_iter()
+                    .map(|(player, bank)| (player, bank.as_millis() as u64))
+                    .collect()
+            }),
+        });
+    }
+
+    // Rebuilds the game state after the first [steps] entries, leaving out those undone by then.
+    pub fn replay<T: Game>(&self, steps: usize) -> Result<T> {
+        if steps > self.entries.len() {
+            return Err(Error::InvalidReplayStep);
+        }
+        let config =
+            serde_json::from_value(self.config.clone()).map_err(|_| Error::ParseFailure)?;
+        let mut game_state = T::new(config, self.players, &mut game_rng(self.seed, 0))?;
+        for (change, (_, entry)) in self.live_entries(steps).into_iter().enumerate() {
+            let mut rng = game_rng(self.seed, change as u64 + 1);
+            match &entry.event {
+                LogEvent::Action {
+                    player_id, action, ..
+                } => {
+                    let action =
+                        serde_json::from_value(action.clone()).map_err(|_| Error::ParseFailure)?;
+                    game_state.do_action(*player_id, &action, &mut rng, &mut Vec::new())?;
+                }
+                LogEvent::Timeout { player_id } => {
+                    game_state.on_timeout(*player_id, &mut rng, &mut Vec::new())?
+                }
+                LogEvent::Tick { now_ms } => {
+                    let now = UNIX_EPOCH + Duration::from_millis(*now_ms);
+                    game_state.tick(now, &mut rng, &mut Vec::new())?
+                }
+                LogEvent::Undo { .. } => unreachable!("undos are not live entries"),
+            }
+        }
+        Ok(game_state)
+    }
+}
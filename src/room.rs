@@ -1,12 +1,25 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::time::Instant;
 
+use crate::chat::{ChatChannel, ChatEntry, ChatMessage};
+use crate::clock::{Clock, PlayerClock};
 use crate::error::Error;
-use crate::game::Game;
+use crate::game::{game_rng, random_seed, Game, GameRng, Outcome};
 use crate::ids::{PlayerId, ReconnectToken, UserId};
-use crate::protocol::UserInfo;
+use crate::options::RoomOptions;
+use crate::protocol::{
+    LobbyInfo, PatchFormat, SeatingInfo, SeatingPolicy, UndoPolicy, UndoRequestInfo, UserInfo,
+};
+use crate::replay::{timestamp_ms, GameLog, LogEvent};
 use crate::result::Result;
 
 #[derive(Debug)]
@@ -15,41 +28,151 @@ pub enum JoinInfo {
     ReconnectToken(ReconnectToken),
 }
 
+// Externally tagged: an internally tagged enum buffers its fields, which loses serde_json's
+// handling of the ids used as map keys.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "", deserialize = "T: DeserializeOwned"))]
+#[serde(rename_all = "snake_case")]
 pub enum RoomState<T: Game> {
     Lobby {
         config: T::Config,
     },
     Game {
         game_state: T,
+        // Config the game was created with, which the lobby gets back after the game
+        config: T::Config,
+        player_mapping: HashMap<UserId, PlayerId>,
+    },
+    // The game reported an outcome; the final state is kept around so it can still be viewed.
+    Finished {
+        game_state: T,
+        config: T::Config,
         player_mapping: HashMap<UserId, PlayerId>,
+        outcome: Outcome,
     },
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct UserData {
     pub id: UserId,
     pub username: String,
     pub token: ReconnectToken,
+    // Whether the user chose to watch rather than play, kept so they stay out of the seats when
+    // they rejoin
+    pub spectating: bool,
+    // Number of open connections for this user
+    #[serde(skip)]
+    pub connections: u32,
+    // When the last connection for this user was closed
+    #[serde(skip)]
+    pub disconnected_at: Option<Instant>,
+}
+
+impl UserData {
+    pub fn connected(&self) -> bool {
+        self.connections > 0
+    }
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct UndoRequest {
+    requester: UserId,
+    actions: u32,
+    approvals: Vec<UserId>,
+}
+
+// Serializing a room produces a snapshot that [Room::restore] can turn back into a room. Connection
+// state is not part of the snapshot.
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "", deserialize = "T: DeserializeOwned"))]
 pub struct Room<T: Game> {
-    // First user is the lobby leader
+    // Users in join order
     users: Vec<UserId>,
+    leader: Option<UserId>,
+    // Users that will be players when the game starts, in seat order. Everyone else spectates.
+    seats: Vec<UserId>,
+    // Users in the order they first sat down, which join-order seating follows
+    sat_down: Vec<UserId>,
+    seating_policy: SeatingPolicy,
+    // Seed used to shuffle seats for the current game, if seating is random
+    seating_seed: Option<u64>,
+    // Log of the current or most recent game
+    game_log: Option<GameLog>,
+    undo_policy: UndoPolicy,
+    undo_request: Option<UndoRequest>,
+    // Most recent chat messages, oldest first
+    chat: VecDeque<ChatEntry>,
+    next_chat_id: u64,
+    muted: HashSet<UserId>,
     // Map may contain users that are not currently connected, but might reconnect later
     user_data: HashMap<UserId, UserData>,
     state: RoomState<T>,
+    // Timers for the running game, if it has any. They pause while nobody is expected to move.
+    clock: Option<Clock>,
+    // Events emitted since they were last taken
+    #[serde(skip)]
+    events: Vec<T::Event>,
     next_user_id: UserId,
+    #[serde(skip)]
+    options: RoomOptions,
+    #[serde(skip, default = "Instant::now")]
+    created_at: Instant,
+}
+
+impl<T: Game> Default for Room<T> {
+    fn default() -> Self {
+        Self::new(RoomOptions::default())
+    }
 }
 
 impl<T: Game> Room<T> {
-    pub fn new() -> Self {
+    pub fn new(options: RoomOptions) -> Self {
         Self {
             users: Vec::new(),
+            leader: None,
+            seats: Vec::new(),
+            sat_down: Vec::new(),
+            seating_policy: SeatingPolicy::default(),
+            seating_seed: None,
+            game_log: None,
+            undo_policy: UndoPolicy::default(),
+            undo_request: None,
+            chat: VecDeque::new(),
+            next_chat_id: 0,
+            muted: HashSet::new(),
             user_data: HashMap::new(),
             state: RoomState::Lobby {
                 config: T::Config::default(),
             },
+            clock: None,
+            events: Vec::new(),
             next_user_id: UserId(0),
+            options,
+            created_at: Instant::now(),
+        }
+    }
+
+    // Rebuilds a room from a snapshot. Everyone starts out disconnected.
+    pub fn restore(snapshot: Value, options: RoomOptions) -> Result<Self>
+    where
+        T: DeserializeOwned,
+    {
+        let mut room: Self = serde_json::from_value(snapshot).map_err(|_| Error::ParseFailure)?;
+        let now = Instant::now();
+        for data in room.user_data.values_mut() {
+            data.disconnected_at = Some(now);
         }
+        room.options = options;
+        room.created_at = now;
+        Ok(room)
+    }
+
+    pub fn snapshot(&self) -> Value {
+        serde_json::to_value(self).unwrap()
+    }
+
+    pub fn options(&self) -> &RoomOptions {
+        &self.options
     }
 
     pub fn join_room(&mut self, join_info: JoinInfo) -> Result<&UserData> {
@@ -76,33 +199,156 @@ impl<T: Game> Room<T> {
                             id: user_id,
                             username: username.to_string(),
                             token: ReconnectToken::new(),
+                            spectating: false,
+                            connections: 1,
+                            disconnected_at: None,
                         },
                     );
-                    let result = self.user_data.get(&user_id).unwrap();
                     self.users.push(user_id);
-                    break Ok(result);
+                    self.sit_down(user_id);
+                    self.leader.get_or_insert(user_id);
+                    break Ok(self.user_data.get(&user_id).unwrap());
                 }
             }
             JoinInfo::ReconnectToken(token) => {
-                match self.user_data.values().find(|data| data.token == token) {
-                    Some(data) => {
-                        if !self.users.contains(&data.id) {
-                            self.users.push(data.id.clone());
-                        }
-                        Ok(data)
+                let user_id = self
+                    .user_data
+                    .values()
+                    .find(|data| data.token == token)
+                    .map(|data| data.id)
+                    .ok_or(Error::InvalidReconnectToken)?;
+                if !self.users.contains(&user_id) {
+                    self.users.push(user_id);
+                    if !self.user_data[&user_id].spectating {
+                        self.sit_down(user_id);
                     }
-                    None => Err(Error::InvalidReconnectToken),
                 }
+                self.leader.get_or_insert(user_id);
+                let data = self.user_data.get_mut(&user_id).unwrap();
+                data.connections += 1;
+                data.disconnected_at = None;
+                Ok(data)
             }
         }
     }
 
-    pub fn user_leader(&self) -> Result<&UserId> {
-        if self.users.len() > 0 {
-            Ok(&self.users[0])
-        } else {
-            Err(Error::EmptyLobby)
+    pub fn disconnect(&mut self, user: &UserId, now: Instant) -> Result<()> {
+        let data = self.user_data.get_mut(user).ok_or(Error::UserNotFound)?;
+        data.connections = data.connections.saturating_sub(1);
+        if data.connections == 0 {
+            data.disconnected_at = Some(now);
+        }
+        Ok(())
+    }
+
+    fn holds_seat(&self, user: &UserId) -> bool {
+        match &self.state {
+            RoomState::Lobby { .. } => false,
+            RoomState::Game { player_mapping, .. } | RoomState::Finished { player_mapping, .. } => {
+                player_mapping.contains_key(user)
+            }
+        }
+    }
+
+    fn disconnect_deadline(&self, user: &UserId) -> Option<Instant> {
+        if self.holds_seat(user) {
+            return None;
+        }
+        let disconnected_at = self.user_data.get(user)?.disconnected_at?;
+        Some(disconnected_at + self.options.disconnect_grace)
+    }
+
+    // Earliest time at which [expire_disconnected] has something to do.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.users
+            .iter()
+            .filter_map(|user| self.disconnect_deadline(user))
+            .min()
+    }
+
+    // Drops users whose grace period has run out. Their data is kept so that they can still
+    // rejoin with their reconnect token. Returns whether any user was dropped.
+    pub fn expire_disconnected(&mut self, now: Instant) -> bool {
+        let expired: Vec<UserId> = self
+            .users
+            .iter()
+            .filter(
+                |user| matches!(self.disconnect_deadline(user), Some(deadline) if deadline <= now),
+            )
+            .copied()
+            .collect();
+        self.users.retain(|user| !expired.contains(user));
+        self.seats.retain(|user| !expired.contains(user));
+        if let Some(leader) = self.leader {
+            if expired.contains(&leader) {
+                self.leader = self.next_leader(&leader).or(self.users.first().copied());
+            }
+        }
+        !expired.is_empty()
+    }
+
+    // Time after which the room should be shut down because nobody is connected to it.
+    pub fn idle_deadline(&self) -> Option<Instant> {
+        if self.user_data.values().any(UserData::connected) {
+            return None;
+        }
+        let last_seen = self
+            .user_data
+            .values()
+            .filter_map(|data| data.disconnected_at)
+            .max()
+            .unwrap_or(self.created_at);
+        Some(last_seen + self.options.idle_ttl)
+    }
+
+    // First connected user after [current] in join order, wrapping around.
+    fn next_leader(&self, current: &UserId) -> Option<UserId> {
+        let start = self
+            .users
+            .iter()
+            .position(|user| user == current)
+            .map_or(0, |index| index + 1);
+        self.users[start..]
+            .iter()
+            .chain(self.users[..start].iter())
+            .find(|user| {
+                *user != current && self.user_data.get(user).is_some_and(UserData::connected)
+            })
+            .copied()
+    }
+
+    // Earliest time at which [hand_over_leadership] has something to do. Only set while there
+    // is a connected user who could take over.
+    pub fn next_leader_handover(&self) -> Option<Instant> {
+        let leader = self.leader?;
+        let disconnected_at = self.user_data.get(&leader)?.disconnected_at?;
+        self.next_leader(&leader)?;
+        Some(disconnected_at + self.options.leader_timeout)
+    }
+
+    // Moves leadership away from a leader that has been disconnected for too long. Returns
+    // whether the leader changed.
+    pub fn hand_over_leadership(&mut self, now: Instant) -> bool {
+        match (self.next_leader_handover(), self.leader) {
+            (Some(deadline), Some(leader)) if deadline <= now => {
+                self.leader = self.next_leader(&leader);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn transfer_leadership(&mut self, user: &UserId, target: &UserId) -> Result<()> {
+        self.ensure_leader(user)?;
+        if !self.users.contains(target) {
+            return Err(Error::UserNotFound);
         }
+        self.leader = Some(*target);
+        Ok(())
+    }
+
+    pub fn user_leader(&self) -> Result<&UserId> {
+        self.leader.as_ref().ok_or(Error::EmptyLobby)
     }
 
     pub fn active_users(&self) -> &Vec<UserId> {
@@ -114,13 +360,15 @@ impl<T: Game> Room<T> {
         // TODO: consider getting extra users from [user_data] instead
         match &self.state {
             RoomState::Lobby { .. } => (),
-            RoomState::Game { player_mapping, .. } => users.extend(player_mapping.keys()),
+            RoomState::Game { player_mapping, .. } | RoomState::Finished { player_mapping, .. } => {
+                users.extend(player_mapping.keys())
+            }
         }
         users.into_iter().collect()
     }
 
     fn ensure_leader(&self, user: &UserId) -> Result<()> {
-        if self.users.len() > 0 && self.users[0] == *user {
+        if self.leader == Some(*user) {
             Ok(())
         } else {
             Err(Error::UserNotLeader)
@@ -130,6 +378,7 @@ impl<T: Game> Room<T> {
     pub fn update_config(&mut self, user: &UserId, new_config: T::Config) -> Result<()> {
         self.ensure_leader(user)?;
         if let RoomState::Lobby { ref mut config, .. } = self.state {
+            T::validate_config(&new_config)?;
             *config = new_config;
             Ok(())
         } else {
@@ -137,18 +386,225 @@ impl<T: Game> Room<T> {
         }
     }
 
+    // Applies [patch] to the current config, then updates it as [update_config] would.
+    pub fn patch_config(&mut self, user: &UserId, patch: Value, format: PatchFormat) -> Result<()> {
+        self.ensure_leader(user)?;
+        let config = self.lobby_config().ok_or(Error::GameAlreadyStarted)?;
+        let mut config = serde_json::to_value(config).unwrap();
+        match format {
+            PatchFormat::Merge => json_patch::merge(&mut config, &patch),
+            PatchFormat::Json => {
+                let patch = json_patch::from_value(patch)
+                    .map_err(|err| Error::InvalidPatch(err.to_string()))?;
+                json_patch::patch(&mut config, &patch)
+                    .map_err(|err| Error::InvalidPatch(err.to_string()))?;
+            }
+        }
+        let config = serde_json::from_value(config).map_err(|_| Error::ParseFailure)?;
+        self.update_config(user, config)
+    }
+
     pub fn kick_user(&mut self, user: &UserId, target: &UserId) -> Result<()> {
         self.ensure_leader(user)?;
         match &self.state {
             RoomState::Lobby { .. } => (),
-            RoomState::Game { player_mapping, .. } => {
+            RoomState::Game { player_mapping, .. } | RoomState::Finished { player_mapping, .. } => {
                 if player_mapping.contains_key(target) {
                     return Err(Error::UserIsPlayer);
                 }
             }
         }
         let _ = self.user_data.remove(target).ok_or(Error::UserNotFound)?;
+        if self.leader == Some(*target) {
+            self.leader = self.next_leader(target);
+        }
         self.users.retain(|u| *u != *target);
+        self.seats.retain(|u| *u != *target);
+        self.sat_down.retain(|u| *u != *target);
+        self.muted.remove(target);
+        Ok(())
+    }
+
+    pub fn mute_user(&mut self, user: &UserId, target: &UserId, muted: bool) -> Result<()> {
+        self.ensure_leader(user)?;
+        if !self.user_data.contains_key(target) {
+            return Err(Error::UserNotFound);
+        }
+        if muted {
+            self.muted.insert(*target);
+        } else {
+            self.muted.remove(target);
+        }
+        Ok(())
+    }
+
+    // Who may read a message [user] sends on [channel]. While a game is running, spectators can
+    // neither use nor read player channels, so that they cannot pass on what they see.
+    fn chat_recipients(&self, user: &UserId, channel: ChatChannel) -> Result<Option<Vec<UserId>>> {
+        let (game, running) = match &self.state {
+            RoomState::Lobby { .. } => (None, false),
+            RoomState::Game {
+                game_state,
+                player_mapping,
+                ..
+            } => (Some((game_state, player_mapping)), true),
+            RoomState::Finished {
+                game_state,
+                player_mapping,
+                ..
+            } => (Some((game_state, player_mapping)), false),
+        };
+        match channel {
+            ChatChannel::Room => match game.filter(|_| running) {
+                // Spectators only talk among themselves while the game runs
+                Some((_, player_mapping)) if !player_mapping.contains_key(user) => Ok(Some(
+                    self.users
+                        .iter()
+                        .filter(|user| !player_mapping.contains_key(user))
+                        .copied()
+                        .collect(),
+                )),
+                _ => Ok(None),
+            },
+            ChatChannel::Players => {
+                let (_, player_mapping) = game.ok_or(Error::GameNotStarted)?;
+                if !player_mapping.contains_key(user) {
+                    return Err(Error::UserNotInGame);
+                }
+                Ok(Some(player_mapping.keys().copied().collect()))
+            }
+            ChatChannel::Team => {
+                let (game_state, player_mapping) = game.ok_or(Error::GameNotStarted)?;
+                let player = player_mapping.get(user).ok_or(Error::UserNotInGame)?;
+                let team = game_state.team(*player).ok_or(Error::NoTeam)?;
+                Ok(Some(
+                    player_mapping
+                        .iter()
+                        .filter(|(_, player)| game_state.team(**player) == Some(team))
+                        .map(|(user, _)| *user)
+                        .collect(),
+                ))
+            }
+            ChatChannel::Whisper { to } => {
+                if to == *user {
+                    return Err(Error::WhisperToSelf);
+                }
+                if !self.user_data.contains_key(&to) {
+                    return Err(Error::UserNotFound);
+                }
+                if let Some((_, player_mapping)) = game.filter(|_| running) {
+                    if player_mapping.contains_key(user) != player_mapping.contains_key(&to) {
+                        return Err(Error::WhisperAcrossSeats);
+                    }
+                }
+                Ok(Some(vec![*user, to]))
+            }
+        }
+    }
+
+    // Checks and records a chat message, returning it as it should be sent.
+    pub fn chat(&mut self, user: &UserId, text: String, channel: ChatChannel) -> Result<ChatEntry> {
+        let username = self
+            .user_data
+            .get(user)
+            .ok_or(Error::UserNotFound)?
+            .username
+            .clone();
+        if self.muted.contains(user) {
+            return Err(Error::Muted);
+        }
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            return Err(Error::EmptyMessage);
+        }
+        if text.chars().count() > self.options.chat_max_length {
+            return Err(Error::MessageTooLong(self.options.chat_max_length));
+        }
+        let recipients = self.chat_recipients(user, channel)?;
+        let text = match &self.options.chat_filter {
+            Some(chat_filter) => chat_filter.filter(*user, text)?,
+            None => text,
+        };
+        let message = ChatMessage {
+            id: self.next_chat_id,
+            from: *user,
+            username,
+            text,
+            channel,
+            timestamp_ms: timestamp_ms(SystemTime::now()),
+        };
+        let entry = ChatEntry {
+            message,
+            recipients,
+        };
+        self.next_chat_id += 1;
+        self.chat.push_back(entry.clone());
+        while self.chat.len() > self.options.chat_history {
+            self.chat.pop_front();
+        }
+        Ok(entry)
+    }
+
+    // Recent chat that [user] may read.
+    pub fn chat_history(&self, user: &UserId) -> Vec<ChatMessage> {
+        self.chat
+            .iter()
+            .filter(|entry| entry.visible_to(user))
+            .map(|entry| entry.message.clone())
+            .collect()
+    }
+
+    pub fn set_spectating(&mut self, user: &UserId, spectating: bool) -> Result<()> {
+        if !matches!(self.state, RoomState::Lobby { .. }) {
+            return Err(Error::GameAlreadyStarted);
+        }
+        if !self.users.contains(user) {
+            return Err(Error::UserNotFound);
+        }
+        if let Some(data) = self.user_data.get_mut(user) {
+            data.spectating = spectating;
+        }
+        if spectating {
+            self.seats.retain(|u| u != user);
+        } else {
+            self.sit_down(*user);
+        }
+        Ok(())
+    }
+
+    // Gives [user] a seat if they do not have one.
+    fn sit_down(&mut self, user: UserId) {
+        if !self.sat_down.contains(&user) {
+            self.sat_down.push(user);
+        }
+        if !self.seats.contains(&user) {
+            self.seats.push(user);
+        }
+        if self.seating_policy == SeatingPolicy::JoinOrder {
+            self.sort_seats();
+        }
+    }
+
+    // Puts seats in the order their users first sat down.
+    fn sort_seats(&mut self) {
+        let sat_down = &self.sat_down;
+        self.seats
+            .sort_by_key(|seat| sat_down.iter().position(|user| user == seat));
+    }
+
+    pub fn set_seat_order(&mut self, user: &UserId, order: Vec<UserId>) -> Result<()> {
+        self.ensure_leader(user)?;
+        if !matches!(self.state, RoomState::Lobby { .. }) {
+            return Err(Error::GameAlreadyStarted);
+        }
+        if self.seating_policy != SeatingPolicy::LeaderDefined {
+            return Err(Error::SeatOrderNotEditable);
+        }
+        let seated = HashSet::<&UserId>::from_iter(self.seats.iter());
+        if order.len() != self.seats.len() || HashSet::from_iter(order.iter()) != seated {
+            return Err(Error::InvalidSeatOrder);
+        }
+        self.seats = order;
         Ok(())
     }
 
@@ -161,6 +617,7 @@ impl<T: Game> Room<T> {
         self.ensure_leader(user)?;
         match &mut self.state {
             RoomState::Lobby { .. } => Err(Error::GameNotStarted),
+            RoomState::Finished { .. } => Err(Error::GameFinished),
             RoomState::Game { player_mapping, .. } => {
                 if player_mapping.contains_key(to_user) {
                     return Err(Error::UserIsAlreadyPlayer(*to_user));
@@ -169,99 +626,1461 @@ impl<T: Game> Room<T> {
                 match player_id {
                     Some(player_id) => {
                         player_mapping.insert(*to_user, player_id);
+                        // Replays show the game from the view of whoever ended up playing
+                        if let Some(game_log) = &mut self.game_log {
+                            for (user, player) in game_log.player_mapping.iter_mut() {
+                                if *player == player_id {
+                                    *user = *to_user;
+                                }
+                            }
+                        }
                         Ok(())
                     }
-                    None => return Err(Error::UserIsNotPlayer(*from_user)),
+                    None => Err(Error::UserIsNotPlayer(*from_user)),
                 }
             }
         }
     }
 
-    pub fn start_game(&mut self, user: &UserId) -> Result<()> {
+    pub fn set_seating_policy(&mut self, user: &UserId, policy: SeatingPolicy) -> Result<()> {
+        self.ensure_leader(user)?;
+        if !matches!(self.state, RoomState::Lobby { .. }) {
+            return Err(Error::GameAlreadyStarted);
+        }
+        self.seating_policy = policy;
+        if policy == SeatingPolicy::JoinOrder {
+            self.sort_seats();
+        }
+        Ok(())
+    }
+
+    pub fn set_undo_policy(&mut self, user: &UserId, policy: UndoPolicy) -> Result<()> {
         self.ensure_leader(user)?;
-        if let RoomState::Lobby { config, .. } = &self.state {
-            let game_state = T::new(config.clone(), self.users.len() as u32)?;
-            let players = HashSet::<PlayerId>::from_iter(T::players(&game_state).into_iter());
-            if players.len() != self.users.len() {
-                return Err(Error::WrongPlayerCount);
+        if !matches!(self.state, RoomState::Lobby { .. }) {
+            return Err(Error::GameAlreadyStarted);
+        }
+        self.undo_policy = policy;
+        Ok(())
+    }
+
+    pub fn seating_seed(&self) -> Option<u64> {
+        self.seating_seed
+    }
+
+    // How the current game's users were matched with its players.
+    pub fn seating_info(&self) -> Option<SeatingInfo> {
+        let (game_state, player_mapping) = match &self.state {
+            RoomState::Lobby { .. } => return None,
+            RoomState::Game {
+                game_state,
+                player_mapping,
+                ..
             }
-            let player_mapping =
-                HashMap::from_iter(self.users.clone().into_iter().zip(players.into_iter()));
-            self.state = RoomState::Game {
+            | RoomState::Finished {
                 game_state,
                 player_mapping,
-            };
-            Ok(())
-        } else {
-            Err(Error::GameAlreadyStarted)
+                ..
+            } => (game_state, player_mapping),
+        };
+        let players = game_state
+            .players()
+            .into_iter()
+            .filter_map(|player| {
+                player_mapping
+                    .iter()
+                    .find(|(_, p)| **p == player)
+                    .map(|(user, _)| (*user, player))
+            })
+            .collect();
+        Some(SeatingInfo {
+            seed: self.seating_seed,
+            players,
+        })
+    }
+
+    // Seated users in the order they will be matched with players, and the seed they were shuffled
+    // with if seating is random.
+    fn seat_order(&self) -> (Vec<UserId>, Option<u64>) {
+        let mut order = self.seats.clone();
+        match self.seating_policy {
+            SeatingPolicy::Random => {
+                let seed = random_seed();
+                // Like games, seating draws from ChaCha so the recorded seed rebuilds it exactly
+                order.shuffle(&mut GameRng::seed_from_u64(seed));
+                (order, Some(seed))
+            }
+            SeatingPolicy::JoinOrder | SeatingPolicy::LeaderDefined => (order, None),
         }
     }
 
-    pub fn reset_to_lobby(&mut self, user: &UserId) -> Result<()> {
-        self.ensure_leader(user)?;
-        self.state = RoomState::Lobby { config: T::Config::default() };
+    // Whether the lobby as it stands could start a game.
+    pub fn can_start(&self) -> Result<()> {
+        let config = self.lobby_config().ok_or(Error::GameAlreadyStarted)?;
+        Self::check_start(config, self.seats.len())
+    }
+
+    fn check_start(config: &T::Config, players: usize) -> Result<()> {
+        T::validate_config(config)?;
+        let range = T::player_range(config);
+        if !range.contains(&(players as u32)) {
+            return Err(Error::PlayerCountOutOfRange(*range.start(), *range.end()));
+        }
         Ok(())
     }
 
-    pub fn user_view<'a>(&'a self, user: &UserId) -> Result<T::View<'a>> {
-        if let RoomState::Game {
+    pub fn start_game(&mut self, user: &UserId) -> Result<()> {
+        self.ensure_leader(user)?;
+        self.can_start()?;
+        let config = self
+            .lobby_config()
+            .ok_or(Error::GameAlreadyStarted)?
+            .clone();
+        let (seat_order, seating_seed) = self.seat_order();
+        self.begin_game(config, seat_order, seating_seed)
+    }
+
+    // Starts a new game after a finished one with the same config and players, with seats rotated
+    // so that the player who went second now goes first. Everyone else spectates.
+    pub fn rematch(&mut self, user: &UserId) -> Result<()> {
+        self.ensure_leader(user)?;
+        match self.state {
+            RoomState::Lobby { .. } => return Err(Error::GameNotStarted),
+            RoomState::Game { .. } => return Err(Error::GameNotFinished),
+            RoomState::Finished { .. } => {}
+        }
+        let mut seat_order = self.game_seat_order().ok_or(Error::GameNotStarted)?;
+        seat_order.rotate_left(1);
+        let config = self.game_config().clone();
+        Self::check_start(&config, seat_order.len())?;
+        self.seat_players(seat_order.clone());
+        self.begin_game(config, seat_order, None)
+    }
+
+    // Sits exactly [players] down, in order, with everyone else spectating.
+    fn seat_players(&mut self, players: Vec<UserId>) {
+        for (user, data) in self.user_data.iter_mut() {
+            data.spectating = !players.contains(user);
+        }
+        self.seats = players;
+    }
+
+    // Users playing the current or finished game, in the game's player order.
+    fn game_seat_order(&self) -> Option<Vec<UserId>> {
+        match &self.state {
+            RoomState::Lobby { .. } => None,
+            RoomState::Game {
+                game_state,
+                player_mapping,
+                ..
+            }
+            | RoomState::Finished {
+                game_state,
+                player_mapping,
+                ..
+            } => Some(
+                game_state
+                    .players()
+                    .iter()
+                    .filter_map(|player| {
+                        player_mapping
+                            .iter()
+                            .find(|(_, p)| *p == player)
+                            .map(|(user, _)| *user)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn begin_game(
+        &mut self,
+        config: T::Config,
+        seat_order: Vec<UserId>,
+        seating_seed: Option<u64>,
+    ) -> Result<()> {
+        let seed = self.options.game_seed.unwrap_or_else(random_seed);
+        let game_state = T::new(
+            config.clone(),
+            seat_order.len() as u32,
+            &mut game_rng(seed, 0),
+        )?;
+        let players = T::players(&game_state);
+        if players.len() != seat_order.len()
+            || HashSet::<&PlayerId>::from_iter(players.iter()).len() != players.len()
+        {
+            return Err(Error::WrongPlayerCount);
+        }
+        // Seats are matched with players in the order the game lists them
+        let num_players = seat_order.len() as u32;
+        let player_mapping = HashMap::from_iter(seat_order.into_iter().zip(players));
+        self.seating_seed = seating_seed;
+        self.game_log = Some(GameLog::new::<T>(
+            &config,
+            num_players,
+            seed,
+            self.seating_seed,
+            player_mapping.iter().map(|(u, p)| (*u, *p)).collect(),
+        ));
+        self.undo_request = None;
+        let clock = T::time_control(&config);
+        self.state = RoomState::Game {
             game_state,
+            config,
             player_mapping,
-        } = &self.state
-        {
-            Ok(T::view(&game_state, player_mapping.get(user).copied()))
-        } else {
-            Err(Error::GameNotStarted)
+        };
+        // A game can be over as soon as it is created
+        self.check_outcome();
+        if matches!(self.state, RoomState::Game { .. }) {
+            let running = self.players_to_move();
+            self.clock = clock.map(|time_control| {
+                Clock::new(time_control, &self.game_players(), running, Instant::now())
+            });
+        }
+        Ok(())
+    }
+
+    // Config of the current or finished game, or the lobby's config outside of games.
+    pub fn game_config(&self) -> &T::Config {
+        match &self.state {
+            RoomState::Lobby { config }
+            | RoomState::Game { config, .. }
+            | RoomState::Finished { config, .. } => config,
+        }
+    }
+
+    // Returns to the lobby with the last game's config. With [reseat], exactly the last game's
+    // players sit down, in their playing order even under [SeatingPolicy::JoinOrder], and everyone
+    // else spectates; otherwise seats stay as they were.
+    pub fn reset_to_lobby(&mut self, user: &UserId, reseat: bool) -> Result<()> {
+        self.ensure_leader(user)?;
+        if reseat {
+            if let Some(players) = self.game_seat_order() {
+                self.seat_players(players);
+            }
+        }
+        self.state = RoomState::Lobby {
+            config: self.game_config().clone(),
+        };
+        self.clock = None;
+        self.undo_request = None;
+        Ok(())
+    }
+
+    // The view of [player], or the spectator view for None.
+    pub fn player_view(&self, player: Option<PlayerId>) -> Result<T::View<'_>> {
+        match &self.state {
+            RoomState::Lobby { .. } => Err(Error::GameNotStarted),
+            RoomState::Game { game_state, .. } | RoomState::Finished { game_state, .. } => {
+                Ok(T::view(game_state, player))
+            }
+        }
+    }
+
+    // None if the game does not publish legal actions or the viewer is not a player.
+    pub fn player_legal_actions(&self, player: Option<PlayerId>) -> Result<Option<Vec<T::Action>>> {
+        if !T::PUBLISH_LEGAL_ACTIONS {
+            return Ok(None);
+        }
+        match &self.state {
+            RoomState::Lobby { .. } => Err(Error::GameNotStarted),
+            RoomState::Game { game_state, .. } => {
+                Ok(player.map(|player| game_state.legal_actions(player)))
+            }
+            RoomState::Finished { .. } => Ok(player.map(|_| Vec::new())),
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<T::Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    // [events] as [viewer] sees them, serialized.
+    pub fn viewer_events(&self, events: &[T::Event], viewer: Option<PlayerId>) -> Vec<Value> {
+        match &self.state {
+            RoomState::Game { game_state, .. } | RoomState::Finished { game_state, .. } => events
+                .iter()
+                .filter_map(|event| game_state.event_view(event, viewer))
+                .map(|event| serde_json::to_value(event).unwrap())
+                .collect(),
+            RoomState::Lobby { .. } => Vec::new(),
+        }
+    }
+
+    // See [Game::changed_views].
+    pub fn changed_views(&self) -> Option<Vec<Option<PlayerId>>> {
+        match &self.state {
+            RoomState::Game { game_state, .. } | RoomState::Finished { game_state, .. } => {
+                game_state.changed_views()
+            }
+            RoomState::Lobby { .. } => None,
         }
     }
 
+    pub fn user_connected(&self, user: &UserId) -> bool {
+        self.user_data.get(user).is_some_and(UserData::connected)
+    }
+
     pub fn user_action(&mut self, user: &UserId, action: &T::Action) -> Result<()> {
-        if let RoomState::Game {
-            ref mut game_state,
-            player_mapping,
-        } = &mut self.state
-        {
-            match player_mapping.get(user) {
-                Some(player) => T::do_action(game_state, *player, action),
-                None => Err(Error::UserNotInGame),
+        let rng = self.next_rng();
+        match &mut self.state {
+            RoomState::Lobby { .. } => Err(Error::GameNotStarted),
+            RoomState::Finished { .. } => Err(Error::GameFinished),
+            RoomState::Game {
+                ref mut game_state,
+                player_mapping,
+                ..
+            } => {
+                let mut rng = rng?;
+                let player = *player_mapping.get(user).ok_or(Error::UserNotInGame)?;
+                if let Some(active) = game_state.active_players() {
+                    if !active.contains(&player) {
+                        return Err(Error::NotYourTurn);
+                    }
+                }
+                let mut events = Vec::new();
+                T::do_action(game_state, player, action, &mut rng, &mut events)?;
+                self.events.extend(events);
+                self.log(LogEvent::Action {
+                    user_id: *user,
+                    player_id: player,
+                    action: serde_json::to_value(action).unwrap(),
+                });
+                self.check_outcome();
+                self.advance_clock(Some(player));
+                Ok(())
             }
+        }
+    }
+
+    pub fn player_timeout(&mut self, player: PlayerId) -> Result<()> {
+        let rng = self.next_rng();
+        match &mut self.state {
+            RoomState::Lobby { .. } => Err(Error::GameNotStarted),
+            RoomState::Finished { .. } => Err(Error::GameFinished),
+            RoomState::Game { game_state, .. } => {
+                let mut rng = rng?;
+                let mut events = Vec::new();
+                game_state.on_timeout(player, &mut rng, &mut events)?;
+                self.events.extend(events);
+                self.log(LogEvent::Timeout { player_id: player });
+                self.check_outcome();
+                self.advance_clock(None);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn next_game_wakeup(&self) -> Option<SystemTime> {
+        match &self.state {
+            RoomState::Game { game_state, .. } => game_state.next_wakeup(),
+            RoomState::Lobby { .. } | RoomState::Finished { .. } => None,
+        }
+    }
+
+    // Lets the game advance on its own if its wakeup time has passed. Returns whether it did.
+    pub fn tick(&mut self, now: SystemTime) -> Result<bool> {
+        let rng = self.next_rng();
+        match &mut self.state {
+            RoomState::Game { game_state, .. } => {
+                if game_state.next_wakeup().is_none_or(|wakeup| wakeup > now) {
+                    return Ok(false);
+                }
+                let mut rng = rng?;
+                // The game sees the time as the log keeps it, so that replays tick with the same time
+                let now_ms = timestamp_ms(now);
+                let mut events = Vec::new();
+                game_state.tick(
+                    UNIX_EPOCH + Duration::from_millis(now_ms),
+                    &mut rng,
+                    &mut events,
+                )?;
+                self.events.extend(events);
+                self.log(LogEvent::Tick { now_ms });
+                self.check_outcome();
+                self.advance_clock(None);
+                Ok(true)
+            }
+            RoomState::Lobby { .. } | RoomState::Finished { .. } => Ok(false),
+        }
+    }
+
+    fn next_rng(&self) -> Result<GameRng> {
+        Ok(self.current_log()?.next_rng())
+    }
+
+    fn log(&mut self, event: LogEvent) {
+        let banks = self
+            .clock
+            .as_ref()
+            .and_then(|clock| clock.banks(Instant::now()));
+        if let Some(game_log) = &mut self.game_log {
+            game_log.push(SystemTime::now(), event, banks);
+        }
+        // A pending request was made against the previous state
+        self.undo_request = None;
+    }
+
+    // Users who still have to approve [request].
+    fn undo_approvers(&self, request: &UndoRequest) -> Vec<UserId> {
+        let approvers = match self.undo_policy {
+            UndoPolicy::Disabled => Vec::new(),
+            UndoPolicy::Leader => self.leader.into_iter().collect(),
+            UndoPolicy::Consensus => self
+                .users
+                .iter()
+                .filter(|user| self.user_player(user).is_some())
+                .copied()
+                .collect(),
+        };
+        approvers
+            .into_iter()
+            .filter(|user| *user != request.requester && !request.approvals.contains(user))
+            .collect()
+    }
+
+    // Returns whether the undo happened right away.
+    pub fn request_undo(&mut self, user: &UserId, actions: u32) -> Result<bool> {
+        if self.undo_policy == UndoPolicy::Disabled {
+            return Err(Error::UndoDisabled);
+        }
+        if self.user_player(user).is_none() && self.leader != Some(*user) {
+            return Err(Error::UserIsNotPlayer(*user));
+        }
+        if self.undo_request.is_some() {
+            return Err(Error::UndoPending);
+        }
+        self.undo_start(actions)?;
+        let request = UndoRequest {
+            requester: *user,
+            actions,
+            approvals: Vec::new(),
+        };
+        self.resolve_undo(request)
+    }
+
+    // Returns whether the undo happened.
+    pub fn respond_undo(&mut self, user: &UserId, approve: bool) -> Result<bool> {
+        let request = self.undo_request.take().ok_or(Error::NoUndoRequest)?;
+        if !self.undo_approvers(&request).contains(user) {
+            self.undo_request = Some(request);
+            return Err(Error::NotUndoApprover);
+        }
+        if !approve {
+            return Ok(false);
+        }
+        let mut request = request;
+        request.approvals.push(*user);
+        self.resolve_undo(request)
+    }
+
+    // Withdraws [user]'s own undo request.
+    pub fn cancel_undo(&mut self, user: &UserId) -> Result<()> {
+        match &self.undo_request {
+            None => Err(Error::NoUndoRequest),
+            Some(request) if request.requester != *user => Err(Error::NotUndoRequester),
+            Some(_) => {
+                self.undo_request = None;
+                Ok(())
+            }
+        }
+    }
+
+    // Time at which the pending undo request lapses because an approver it waits on has been
+    // disconnected for longer than the grace period.
+    pub fn undo_deadline(&self) -> Option<Instant> {
+        let request = self.undo_request.as_ref()?;
+        self.undo_approvers(request)
+            .iter()
+            .filter_map(|user| self.user_data.get(user)?.disconnected_at)
+            .min()
+            .map(|disconnected_at| disconnected_at + self.options.disconnect_grace)
+    }
+
+    // Drops the pending undo request once [undo_deadline] has passed. Returns whether it did.
+    pub fn expire_undo(&mut self, now: Instant) -> bool {
+        if self.undo_deadline().is_some_and(|deadline| deadline <= now) {
+            self.undo_request = None;
+            true
         } else {
-            Err(Error::GameNotStarted)
+            false
+        }
+    }
+
+    fn resolve_undo(&mut self, request: UndoRequest) -> Result<bool> {
+        if self.undo_approvers(&request).is_empty() {
+            self.undo(request.actions)?;
+            Ok(true)
+        } else {
+            self.undo_request = Some(request);
+            Ok(false)
+        }
+    }
+
+    // Index of the log entry holding the [actions]th most recent action.
+    fn undo_start(&self, actions: u32) -> Result<usize> {
+        match self.state {
+            RoomState::Lobby { .. } => return Err(Error::GameNotStarted),
+            // The outcome has already gone out to everyone
+            RoomState::Finished { .. } => return Err(Error::GameFinished),
+            RoomState::Game { .. } => (),
+        }
+        let game_log = self.current_log()?;
+        game_log
+            .live_entries(game_log.entries.len())
+            .into_iter()
+            .rev()
+            .filter(|(_, entry)| matches!(entry.event, LogEvent::Action { .. }))
+            .nth(
+                (actions as usize)
+                    .checked_sub(1)
+                    .ok_or(Error::NothingToUndo)?,
+            )
+            .map(|(index, _)| index)
+            .ok_or(Error::NothingToUndo)
+    }
+
+    // Rolls the game back to just before the [actions]th most recent action by replaying the log.
+    // Timeouts and ticks since then are taken back along with the actions, and the log records the
+    // undo.
+    fn undo(&mut self, actions: u32) -> Result<()> {
+        let start = self.undo_start(actions)?;
+        let game_state = self.current_log()?.replay(start)?;
+        let (config, player_mapping) = match &self.state {
+            RoomState::Lobby { .. } => return Err(Error::GameNotStarted),
+            RoomState::Game {
+                config,
+                player_mapping,
+                ..
+            }
+            | RoomState::Finished {
+                config,
+                player_mapping,
+                ..
+            } => (config.clone(), player_mapping.clone()),
+        };
+        let mut banks = None;
+        if let Some(game_log) = &mut self.game_log {
+            banks = game_log.entries[start].banks_ms.clone();
+            game_log.push(SystemTime::now(), LogEvent::Undo { from: start }, None);
+        }
+        self.state = RoomState::Game {
+            game_state,
+            config,
+            player_mapping,
+        };
+        self.undo_request = None;
+        // Players get back the time they spent since the state they return to
+        let running = self.players_to_move();
+        if let (Some(clock), Some(banks)) = (&mut self.clock, banks) {
+            let banks: Vec<(PlayerId, Duration)> = banks
+                .into_iter()
+                .map(|(player, ms)| (player, Duration::from_millis(ms)))
+                .collect();
+            clock.restore(&banks, running, Instant::now());
+        }
+        self.check_outcome();
+        self.advance_clock(None);
+        Ok(())
+    }
+
+    pub fn undo_request(&self) -> Option<UndoRequestInfo> {
+        self.undo_request.as_ref().map(|request| UndoRequestInfo {
+            requester: request.requester,
+            actions: request.actions,
+            waiting_on: self.undo_approvers(request),
+        })
+    }
+
+    // Log of the most recent game. It can't be looked at while the game is running, since the log
+    // reveals everything hidden from the players, such as the seeds.
+    pub fn game_log(&self) -> Result<&GameLog> {
+        match self.state {
+            RoomState::Game { .. } => Err(Error::GameNotFinished),
+            RoomState::Lobby { .. } | RoomState::Finished { .. } => self.current_log(),
+        }
+    }
+
+    fn current_log(&self) -> Result<&GameLog> {
+        self.game_log.as_ref().ok_or(Error::NoGameLog)
+    }
+
+    // The user's view of the most recent game after the first [step] log entries.
+    pub fn replay_view(&self, user: &UserId, step: usize) -> Result<Value> {
+        let game_log = self.game_log()?;
+        let game_state: T = game_log.replay(step)?;
+        let player = game_log
+            .player_mapping
+            .iter()
+            .find(|(u, _)| u == user)
+            .map(|(_, p)| *p);
+        Ok(serde_json::to_value(game_state.view(player)).unwrap())
+    }
+
+    fn check_outcome(&mut self) {
+        if let RoomState::Game { game_state, .. } = &self.state {
+            if let Some(outcome) = game_state.outcome() {
+                self.finish_game(outcome);
+            }
+        }
+    }
+
+    pub fn lobby_config(&self) -> Option<&T::Config> {
+        match &self.state {
+            RoomState::Lobby { config } => Some(config),
+            RoomState::Game { .. } | RoomState::Finished { .. } => None,
+        }
+    }
+
+    pub fn user_player(&self, user: &UserId) -> Option<PlayerId> {
+        match &self.state {
+            RoomState::Lobby { .. } => None,
+            RoomState::Game { player_mapping, .. } | RoomState::Finished { player_mapping, .. } => {
+                player_mapping.get(user).copied()
+            }
+        }
+    }
+
+    pub fn game_players(&self) -> Vec<PlayerId> {
+        match &self.state {
+            RoomState::Game { game_state, .. } | RoomState::Finished { game_state, .. } => {
+                game_state.players()
+            }
+            RoomState::Lobby { .. } => Vec::new(),
+        }
+    }
+
+    // Players that are expected to act, i.e. whose clocks should run.
+    pub fn players_to_move(&self) -> Vec<PlayerId> {
+        match &self.state {
+            RoomState::Game { game_state, .. } => game_state
+                .active_players()
+                .unwrap_or_else(|| game_state.players()),
+            RoomState::Lobby { .. } | RoomState::Finished { .. } => Vec::new(),
+        }
+    }
+
+    // Players whose turn it is, if the game reports turns.
+    pub fn active_players(&self) -> Option<Vec<PlayerId>> {
+        match &self.state {
+            RoomState::Game { game_state, .. } => game_state.active_players(),
+            RoomState::Lobby { .. } | RoomState::Finished { .. } => None,
+        }
+    }
+
+    // Restarts the running clocks after the set of players to move may have changed. [acted] is the
+    // player that made a move, if any.
+    fn advance_clock(&mut self, acted: Option<PlayerId>) {
+        let running = self.players_to_move();
+        // With nobody to move, e.g. while the game waits for a wakeup, the clock just pauses
+        if let Some(clock) = &mut self.clock {
+            if acted.is_some() || *clock.running() != running {
+                clock.update(acted, running, Instant::now());
+            }
+        }
+    }
+
+    // Whether [player]'s time is running.
+    pub fn player_on_clock(&self, player: PlayerId) -> bool {
+        self.clock
+            .as_ref()
+            .is_some_and(|clock| clock.running().contains(&player))
+    }
+
+    pub fn clock_info(&self, now: Instant) -> Option<Vec<PlayerClock>> {
+        self.clock.as_ref().map(|clock| clock.info(now))
+    }
+
+    pub fn next_clock_deadline(&self) -> Option<Instant> {
+        self.clock.as_ref().and_then(Clock::next_deadline)
+    }
+
+    // Players whose time ran out by [now]; see [Clock::expired].
+    pub fn expired_players(&mut self, now: Instant) -> Vec<PlayerId> {
+        match &mut self.clock {
+            Some(clock) => clock.expired(now),
+            None => Vec::new(),
+        }
+    }
+
+    fn finish_game(&mut self, outcome: Outcome) {
+        self.clock = None;
+        let state = std::mem::replace(
+            &mut self.state,
+            RoomState::Lobby {
+                config: T::Config::default(),
+            },
+        );
+        self.state = match state {
+            RoomState::Game {
+                game_state,
+                config,
+                player_mapping,
+            } => RoomState::Finished {
+                game_state,
+                config,
+                player_mapping,
+                outcome,
+            },
+            state => state,
+        };
+    }
+
+    pub fn outcome(&self) -> Option<&Outcome> {
+        match &self.state {
+            RoomState::Finished { outcome, .. } => Some(outcome),
+            _ => None,
         }
     }
 
     pub fn user_info(&self) -> Vec<UserInfo> {
         let Self {
             users,
+            leader,
+            seats,
+            sat_down: _,
+            seating_policy: _,
+            seating_seed: _,
+            game_log: _,
+            undo_policy: _,
+            undo_request: _,
+            chat: _,
+            next_chat_id: _,
+            muted,
             user_data,
             state,
+            clock: _,
+            events: _,
             next_user_id: _,
+            options: _,
+            created_at: _,
         } = &self;
+        let active_players = self.active_players().unwrap_or_default();
         let player_mapping = match state {
             RoomState::Lobby { .. } => None,
-            RoomState::Game { player_mapping, .. } => Some(player_mapping),
+            RoomState::Game { player_mapping, .. } | RoomState::Finished { player_mapping, .. } => {
+                Some(player_mapping)
+            }
         };
         users
             .iter()
             .map(|id| {
-                let user_data = user_data.get(&id).unwrap();
-                UserInfo::new(
-                    *id,
-                    user_data.username.clone(),
-                    *id == users[0],
-                    match player_mapping {
-                        Some(player_mapping) => player_mapping.get(&id).cloned(),
-                        None => None,
-                    },
-                )
+                let user_data = user_data.get(id).unwrap();
+                let player_id = match player_mapping {
+                    Some(player_mapping) => player_mapping.get(id).cloned(),
+                    None => None,
+                };
+                UserInfo {
+                    id: *id,
+                    username: user_data.username.clone(),
+                    leader: Some(*id) == *leader,
+                    player_id,
+                    connected: user_data.connected(),
+                    seat: seats
+                        .iter()
+                        .position(|seat| seat == id)
+                        .map(|seat| seat as u32),
+                    active: player_id.is_some_and(|player| active_players.contains(&player)),
+                    muted: muted.contains(id),
+                }
             })
             .collect()
     }
 
-    pub fn lobby_info(&self) -> Option<Value> {
+    pub fn lobby_info(&self) -> Option<LobbyInfo> {
         match &self.state {
-            RoomState::Lobby { config } => Some(serde_json::to_value(config).unwrap()),
-            RoomState::Game { .. } => None,
+            RoomState::Lobby { config } => Some(LobbyInfo {
+                config: serde_json::to_value(config).unwrap(),
+                seating_policy: self.seating_policy,
+                undo_policy: self.undo_policy,
+                start_error: self.can_start().err().map(|err| err.to_string()),
+            }),
+            RoomState::Game { .. } | RoomState::Finished { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::chat::ChatFilter;
+    use crate::test_game::{room_with_users, TestConfig, TestGame};
+
+    #[test]
+    fn game_over_at_creation_finishes_immediately() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            target: Some(0),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.start_game(&users[0]).unwrap();
+        assert!(room.outcome().is_some());
+        assert!(matches!(
+            room.user_action(&users[0], &1),
+            Err(Error::GameFinished)
+        ));
+    }
+
+    #[test]
+    fn out_of_turn_actions_are_rejected() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        room.start_game(&users[0]).unwrap();
+        let active: Vec<bool> = room.user_info().iter().map(|user| user.active).collect();
+        assert_eq!(active, vec![true, false]);
+        assert!(matches!(
+            room.user_action(&users[1], &5),
+            Err(Error::NotYourTurn)
+        ));
+        let RoomState::Game { game_state, .. } = &room.state else {
+            panic!("game should be running");
+        };
+        assert_eq!((game_state.total, game_state.turn), (0, 0));
+        assert!(room.current_log().unwrap().entries.is_empty());
+
+        room.user_action(&users[0], &5).unwrap();
+        let active: Vec<bool> = room.user_info().iter().map(|user| user.active).collect();
+        assert_eq!(active, vec![false, true]);
+    }
+
+    #[test]
+    fn legal_actions_go_to_players_only() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            target: Some(3),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        assert!(matches!(
+            room.player_legal_actions(Some(PlayerId(0))),
+            Err(Error::GameNotStarted)
+        ));
+        room.start_game(&users[0]).unwrap();
+        assert_eq!(
+            room.player_legal_actions(Some(PlayerId(0))).unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            room.player_legal_actions(Some(PlayerId(1))).unwrap(),
+            Some(Vec::new())
+        );
+        assert_eq!(room.player_legal_actions(None).unwrap(), None);
+
+        // Nobody has anything left to do once the game is over
+        room.user_action(&users[0], &3).unwrap();
+        assert!(room.outcome().is_some());
+        assert_eq!(
+            room.player_legal_actions(Some(PlayerId(1))).unwrap(),
+            Some(Vec::new())
+        );
+        assert_eq!(room.player_legal_actions(None).unwrap(), None);
+    }
+
+    #[test]
+    fn spectators_stay_spectating_after_rejoining() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        room.set_spectating(&users[1], true).unwrap();
+        let token = room.user_data[&users[1]].token.clone();
+        let now = Instant::now();
+        room.disconnect(&users[1], now).unwrap();
+        room.expire_disconnected(now + room.options.disconnect_grace);
+        assert!(!room.users.contains(&users[1]));
+        room.join_room(JoinInfo::ReconnectToken(token)).unwrap();
+        assert!(room.users.contains(&users[1]));
+        assert_eq!(room.seats, vec![users[0]]);
+    }
+
+    #[test]
+    fn join_order_seating_keeps_first_sitting_order() {
+        let (mut room, users) = room_with_users(&["a", "b", "c"]);
+        room.set_seating_policy(&users[0], SeatingPolicy::JoinOrder)
+            .unwrap();
+        room.set_spectating(&users[0], true).unwrap();
+        room.set_spectating(&users[0], false).unwrap();
+        assert_eq!(room.seats, users);
+        room.start_game(&users[0]).unwrap();
+        let seating = room.seating_info().unwrap();
+        assert_eq!(seating.seed, None);
+        assert_eq!(
+            seating.players,
+            vec![
+                (users[0], PlayerId(0)),
+                (users[1], PlayerId(1)),
+                (users[2], PlayerId(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn random_seating_records_seed_only_when_the_game_starts() {
+        let (mut room, users) = room_with_users(&["a", "b", "c"]);
+        room.set_seating_policy(&users[0], SeatingPolicy::Random)
+            .unwrap();
+        assert_eq!(room.seating_info(), None);
+        room.start_game(&users[0]).unwrap();
+        let seating = room.seating_info().unwrap();
+        let seed = seating.seed.unwrap();
+        let mut order = users.clone();
+        order.shuffle(&mut GameRng::seed_from_u64(seed));
+        let seated: Vec<UserId> = seating.players.iter().map(|(user, _)| *user).collect();
+        assert_eq!(seated, order);
+    }
+
+    fn game(room: &Room<TestGame>) -> &TestGame {
+        match &room.state {
+            RoomState::Game { game_state, .. } | RoomState::Finished { game_state, .. } => {
+                game_state
+            }
+            RoomState::Lobby { .. } => panic!("no game in the lobby"),
         }
     }
+
+    fn bank_ms(room: &Room<TestGame>, player: PlayerId) -> Option<u64> {
+        room.clock_info(Instant::now())?
+            .into_iter()
+            .find(|clock| clock.player == player)?
+            .bank_ms
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn undo_gives_back_clock_time() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            bank_ms: Some(60_000),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.start_game(&users[0]).unwrap();
+        tokio::time::advance(Duration::from_secs(10)).await;
+        room.user_action(&users[0], &1).unwrap();
+        tokio::time::advance(Duration::from_secs(20)).await;
+        room.user_action(&users[1], &1).unwrap();
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(bank_ms(&room, PlayerId(0)), Some(45_000));
+        assert_eq!(bank_ms(&room, PlayerId(1)), Some(40_000));
+        assert!(room.request_undo(&users[0], 1).unwrap());
+        assert_eq!(bank_ms(&room, PlayerId(0)), Some(50_000));
+        assert_eq!(bank_ms(&room, PlayerId(1)), Some(40_000));
+        assert_eq!(room.clock.as_ref().unwrap().running(), &vec![PlayerId(1)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn snapshot_restores_game_and_clock() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            bank_ms: Some(60_000),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.start_game(&users[0]).unwrap();
+        tokio::time::advance(Duration::from_secs(10)).await;
+        room.user_action(&users[0], &3).unwrap();
+        tokio::time::advance(Duration::from_secs(5)).await;
+        room.chat(&users[1], "hi".to_string(), ChatChannel::Room)
+            .unwrap();
+
+        let restored = Room::<TestGame>::restore(room.snapshot(), room.options().clone()).unwrap();
+        assert_eq!(restored.seats, room.seats);
+        assert_eq!(restored.seating_info(), room.seating_info());
+        assert_eq!(
+            serde_json::to_value(restored.current_log().unwrap()).unwrap(),
+            serde_json::to_value(room.current_log().unwrap()).unwrap()
+        );
+        assert_eq!(
+            restored.chat_history(&users[0]),
+            room.chat_history(&users[0])
+        );
+        assert_eq!(
+            restored.clock_info(Instant::now()),
+            room.clock_info(Instant::now())
+        );
+        assert_eq!(bank_ms(&restored, PlayerId(1)), Some(55_000));
+        // Everyone has to reconnect
+        assert!(restored.user_info().iter().all(|user| !user.connected));
+    }
+
+    #[test]
+    fn game_log_is_only_available_once_the_game_is_over() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            target: Some(5),
+            wakeup_ms: Some(0),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.start_game(&users[0]).unwrap();
+        let tick_time = SystemTime::now() + Duration::from_secs(1);
+        assert!(room.tick(tick_time).unwrap());
+        room.user_action(&users[0], &2).unwrap();
+        assert!(matches!(room.game_log(), Err(Error::GameNotFinished)));
+        assert!(matches!(
+            room.replay_view(&users[0], 0),
+            Err(Error::GameNotFinished)
+        ));
+
+        room.user_action(&users[1], &3).unwrap();
+        let game_log = room.game_log().unwrap();
+        let replayed: TestGame = game_log.replay(game_log.entries.len()).unwrap();
+        // Ticks replay with the time they originally ran at, not when they were logged
+        assert_eq!(
+            replayed.ticks,
+            vec![UNIX_EPOCH + Duration::from_millis(timestamp_ms(tick_time))]
+        );
+        assert!(room.outcome().is_some());
+        assert_eq!(
+            serde_json::to_value(&replayed).unwrap(),
+            serde_json::to_value(game(&room)).unwrap()
+        );
+        assert!(room.replay_view(&users[1], 1).is_ok());
+
+        // The log stays around once everyone is back in the lobby
+        room.reset_to_lobby(&users[0], false).unwrap();
+        assert_eq!(room.game_log().unwrap().entries.len(), 3);
+        assert!(room.replay_view(&users[1], 1).is_ok());
+    }
+
+    #[test]
+    fn game_log_follows_reassigned_players() {
+        let (mut room, users) = room_with_users(&["a", "b", "c"]);
+        let config = TestConfig {
+            target: Some(5),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.set_spectating(&users[2], true).unwrap();
+        room.start_game(&users[0]).unwrap();
+        let player = room
+            .current_log()
+            .unwrap()
+            .player_mapping
+            .iter()
+            .find(|(user, _)| *user == users[1])
+            .unwrap()
+            .1;
+        room.reassign_player(&users[0], &users[1], &users[2])
+            .unwrap();
+        room.user_action(&users[0], &2).unwrap();
+        room.user_action(&users[2], &3).unwrap();
+
+        let game_log = room.game_log().unwrap();
+        assert!(game_log.player_mapping.contains(&(users[2], player)));
+        assert!(game_log
+            .player_mapping
+            .iter()
+            .all(|(user, _)| *user != users[1]));
+    }
+
+    #[test]
+    fn undo_rolls_back_running_games_only() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            target: Some(5),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.start_game(&users[0]).unwrap();
+        room.user_action(&users[0], &2).unwrap();
+        room.user_action(&users[1], &1).unwrap();
+        let rolls = game(&room).rolls.clone();
+        assert!(room.request_undo(&users[0], 1).unwrap());
+        assert_eq!(room.active_players(), Some(vec![PlayerId(1)]));
+        // The undone action stays in the log
+        let game_log = room.current_log().unwrap();
+        assert_eq!(game_log.entries.len(), 3);
+        assert!(matches!(
+            game_log.entries[2].event,
+            LogEvent::Undo { from: 1 }
+        ));
+        assert_eq!(game_log.live_entries(3).len(), 1);
+
+        // Making the same action again draws the same numbers
+        room.user_action(&users[1], &1).unwrap();
+        assert_eq!(game(&room).rolls, rolls);
+        assert!(room.request_undo(&users[0], 1).unwrap());
+
+        room.user_action(&users[1], &3).unwrap();
+        assert!(room.outcome().is_some());
+        assert!(matches!(
+            room.request_undo(&users[0], 1),
+            Err(Error::GameFinished)
+        ));
+        assert!(room.outcome().is_some());
+    }
+
+    #[test]
+    fn undo_requests_end_when_cancelled_or_an_approver_stays_away() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        room.set_undo_policy(&users[0], UndoPolicy::Consensus)
+            .unwrap();
+        room.start_game(&users[0]).unwrap();
+        room.user_action(&users[0], &1).unwrap();
+        assert!(!room.request_undo(&users[0], 1).unwrap());
+        assert!(matches!(
+            room.cancel_undo(&users[1]),
+            Err(Error::NotUndoRequester)
+        ));
+        room.cancel_undo(&users[0]).unwrap();
+        assert!(room.undo_request().is_none());
+        assert!(matches!(
+            room.cancel_undo(&users[0]),
+            Err(Error::NoUndoRequest)
+        ));
+
+        assert!(!room.request_undo(&users[0], 1).unwrap());
+        assert!(room.undo_deadline().is_none());
+        let now = Instant::now();
+        room.disconnect(&users[1], now).unwrap();
+        let deadline = now + room.options.disconnect_grace;
+        assert_eq!(room.undo_deadline(), Some(deadline));
+        assert!(!room.expire_undo(now));
+        assert!(room.expire_undo(deadline));
+        assert!(room.undo_request().is_none());
+    }
+
+    #[test]
+    fn reseating_seats_only_the_last_games_players() {
+        let (mut room, users) = room_with_users(&["a", "b", "c"]);
+        room.set_spectating(&users[2], true).unwrap();
+        room.start_game(&users[0]).unwrap();
+        let late = room
+            .join_room(JoinInfo::Username("d".to_string()))
+            .unwrap()
+            .id;
+        room.reset_to_lobby(&users[0], true).unwrap();
+        assert_eq!(room.seats, vec![users[0], users[1]]);
+        assert!(room.user_data[&late].spectating);
+        assert!(room.user_data[&users[2]].spectating);
+    }
+
+    #[test]
+    fn merge_patches_change_only_the_given_fields() {
+        let (mut room, users) = room_with_users(&["a"]);
+        let config = TestConfig {
+            target: Some(5),
+            teams: Some(2),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.patch_config(&users[0], json!({ "target": 10 }), PatchFormat::Merge)
+            .unwrap();
+        let config = room.lobby_config().unwrap();
+        assert_eq!(config.target, Some(10));
+        assert_eq!(config.teams, Some(2));
+    }
+
+    #[test]
+    fn json_patches_apply_in_order() {
+        let (mut room, users) = room_with_users(&["a"]);
+        let patch = json!([
+            { "op": "replace", "path": "/target", "value": 7 },
+            { "op": "replace", "path": "/teams", "value": 3 },
+        ]);
+        room.patch_config(&users[0], patch, PatchFormat::Json)
+            .unwrap();
+        let config = room.lobby_config().unwrap();
+        assert_eq!(config.target, Some(7));
+        assert_eq!(config.teams, Some(3));
+    }
+
+    #[test]
+    fn bad_patches_leave_the_config_alone() {
+        let (mut room, users) = room_with_users(&["a"]);
+        let config = TestConfig {
+            target: Some(5),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        assert!(matches!(
+            room.patch_config(&users[0], json!({ "op": "replace" }), PatchFormat::Json),
+            Err(Error::InvalidPatch(_))
+        ));
+        assert!(matches!(
+            room.patch_config(&users[0], json!({ "teams": 0 }), PatchFormat::Merge),
+            Err(Error::InvalidConfig(_))
+        ));
+        let config = room.lobby_config().unwrap();
+        assert_eq!(config.target, Some(5));
+        assert_eq!(config.teams, None);
+    }
+
+    #[test]
+    fn reseating_keeps_the_playing_order() {
+        let (mut room, users) = room_with_users(&["a", "b", "c"]);
+        room.set_seating_policy(&users[0], SeatingPolicy::JoinOrder)
+            .unwrap();
+        room.set_spectating(&users[2], true).unwrap();
+        room.start_game(&users[0]).unwrap();
+        room.reassign_player(&users[0], &users[0], &users[2])
+            .unwrap();
+        room.reset_to_lobby(&users[0], true).unwrap();
+        assert_eq!(room.seats, vec![users[2], users[1]]);
+    }
+
+    #[test]
+    fn the_lobby_gets_the_games_config_back() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            target: Some(5),
+            teams: Some(2),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.start_game(&users[0]).unwrap();
+        // The config is kept with the game rather than read back from the log
+        room.game_log.as_mut().unwrap().config = Value::Null;
+        room.user_action(&users[0], &5).unwrap();
+        room.rematch(&users[0]).unwrap();
+        room.reset_to_lobby(&users[0], false).unwrap();
+        let config = room.lobby_config().unwrap();
+        assert_eq!(config.target, Some(5));
+        assert_eq!(config.teams, Some(2));
+    }
+
+    #[test]
+    fn rematches_follow_finished_games_and_seat_their_players() {
+        let (mut room, mut users) = room_with_users(&["a", "b", "c"]);
+        room.set_spectating(&users[2], true).unwrap();
+        let config = TestConfig {
+            target: Some(1),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        assert!(matches!(
+            room.rematch(&users[0]),
+            Err(Error::GameNotStarted)
+        ));
+        room.start_game(&users[0]).unwrap();
+        assert!(matches!(
+            room.rematch(&users[0]),
+            Err(Error::GameNotFinished)
+        ));
+
+        users.push(
+            room.join_room(JoinInfo::Username("d".to_string()))
+                .unwrap()
+                .id,
+        );
+        room.user_action(&users[0], &1).unwrap();
+        room.rematch(&users[0]).unwrap();
+        assert_eq!(room.seats, vec![users[1], users[0]]);
+        let spectating: Vec<bool> = users
+            .iter()
+            .map(|user| room.user_data[user].spectating)
+            .collect();
+        assert_eq!(spectating, vec![false, false, true, true]);
+    }
+
+    #[derive(Debug)]
+    struct MaskFilter;
+
+    impl ChatFilter for MaskFilter {
+        fn filter(&self, _: UserId, text: String) -> Result<String> {
+            if text.contains("spam") {
+                return Err(Error::MessageRejected("no spam".to_string()));
+            }
+            Ok(text.replace("darn", "****"))
+        }
+    }
+
+    #[test]
+    fn chat_checks_length_and_trims() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        room.options.chat_max_length = 5;
+        let entry = room
+            .chat(&users[0], "  hello  ".to_string(), ChatChannel::Room)
+            .unwrap();
+        assert_eq!(entry.message.text, "hello");
+        assert!(matches!(
+            room.chat(&users[0], "hello!".to_string(), ChatChannel::Room),
+            Err(Error::MessageTooLong(5))
+        ));
+        assert!(matches!(
+            room.chat(&users[0], "   ".to_string(), ChatChannel::Room),
+            Err(Error::EmptyMessage)
+        ));
+    }
+
+    #[test]
+    fn muted_users_cannot_chat() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        assert!(matches!(
+            room.mute_user(&users[1], &users[0], true),
+            Err(Error::UserNotLeader)
+        ));
+        room.mute_user(&users[0], &users[1], true).unwrap();
+        assert!(matches!(
+            room.chat(&users[1], "hi".to_string(), ChatChannel::Room),
+            Err(Error::Muted)
+        ));
+        room.mute_user(&users[0], &users[1], false).unwrap();
+        room.chat(&users[1], "hi".to_string(), ChatChannel::Room)
+            .unwrap();
+    }
+
+    #[test]
+    fn chat_filter_rewrites_or_rejects_messages() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        room.options.chat_filter = Some(Arc::new(MaskFilter));
+        let entry = room
+            .chat(&users[0], "darn it".to_string(), ChatChannel::Room)
+            .unwrap();
+        assert_eq!(entry.message.text, "**** it");
+        assert!(matches!(
+            room.chat(&users[0], "spam".to_string(), ChatChannel::Room),
+            Err(Error::MessageRejected(_))
+        ));
+        // Rejected messages are not kept
+        assert_eq!(room.chat_history(&users[1]).len(), 1);
+    }
+
+    #[test]
+    fn chat_history_keeps_the_latest_messages() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        room.options.chat_history = 2;
+        for text in ["one", "two", "three"] {
+            room.chat(&users[0], text.to_string(), ChatChannel::Room)
+                .unwrap();
+        }
+        let texts: Vec<String> = room
+            .chat_history(&users[1])
+            .into_iter()
+            .map(|message| message.text)
+            .collect();
+        assert_eq!(texts, vec!["two", "three"]);
+    }
+
+    fn chat_texts(room: &Room<TestGame>, user: &UserId) -> Vec<String> {
+        room.chat_history(user)
+            .into_iter()
+            .map(|message| message.text)
+            .collect()
+    }
+
+    #[test]
+    fn chat_channels_are_scoped_while_the_game_runs() {
+        let (mut room, users) = room_with_users(&["a", "b", "c", "d"]);
+        room.set_spectating(&users[3], true).unwrap();
+        let config = TestConfig {
+            teams: Some(2),
+            target: Some(1),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        assert!(matches!(
+            room.chat(&users[0], "x".to_string(), ChatChannel::Players),
+            Err(Error::GameNotStarted)
+        ));
+        room.start_game(&users[0]).unwrap();
+        let [a, b, c, d] = [users[0], users[1], users[2], users[3]];
+
+        room.chat(&a, "players".to_string(), ChatChannel::Players)
+            .unwrap();
+        room.chat(&a, "team".to_string(), ChatChannel::Team)
+            .unwrap();
+        room.chat(&d, "spectators".to_string(), ChatChannel::Room)
+            .unwrap();
+        room.chat(&b, "room".to_string(), ChatChannel::Room)
+            .unwrap();
+        room.chat(&a, "whisper".to_string(), ChatChannel::Whisper { to: b })
+            .unwrap();
+        assert!(matches!(
+            room.chat(&d, "x".to_string(), ChatChannel::Players),
+            Err(Error::UserNotInGame)
+        ));
+        assert!(matches!(
+            room.chat(&d, "x".to_string(), ChatChannel::Whisper { to: a }),
+            Err(Error::WhisperAcrossSeats)
+        ));
+        assert!(matches!(
+            room.chat(&a, "x".to_string(), ChatChannel::Whisper { to: a }),
+            Err(Error::WhisperToSelf)
+        ));
+
+        // a and c share a team
+        assert_eq!(
+            chat_texts(&room, &a),
+            vec!["players", "team", "room", "whisper"]
+        );
+        assert_eq!(chat_texts(&room, &b), vec!["players", "room", "whisper"]);
+        assert_eq!(chat_texts(&room, &c), vec!["players", "team", "room"]);
+        assert_eq!(chat_texts(&room, &d), vec!["spectators", "room"]);
+
+        // Once the game is over spectators talk to everyone again
+        room.user_action(&a, &1).unwrap();
+        room.chat(&d, "gg".to_string(), ChatChannel::Room).unwrap();
+        assert_eq!(chat_texts(&room, &a).last().unwrap(), "gg");
+    }
+
+    #[test]
+    fn leadership_passes_on_after_the_leader_times_out() {
+        let (mut room, users) = room_with_users(&["a", "b", "c"]);
+        let now = Instant::now();
+        room.disconnect(&users[1], now).unwrap();
+        room.disconnect(&users[0], now).unwrap();
+        let timeout = room.options.leader_timeout;
+        assert_eq!(room.next_leader_handover(), Some(now + timeout));
+        assert!(!room.hand_over_leadership(now + timeout - Duration::from_millis(1)));
+        assert_eq!(room.leader, Some(users[0]));
+        // Disconnected users are skipped
+        assert!(room.hand_over_leadership(now + timeout));
+        assert_eq!(room.leader, Some(users[2]));
+    }
+
+    #[test]
+    fn dropping_the_leader_passes_leadership_on() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        room.options.leader_timeout = room.options.disconnect_grace * 2;
+        let now = Instant::now();
+        room.disconnect(&users[0], now).unwrap();
+        assert!(room.expire_disconnected(now + room.options.disconnect_grace));
+        assert!(!room.users.contains(&users[0]));
+        assert_eq!(room.leader, Some(users[1]));
+    }
+
+    #[test]
+    fn only_the_leader_transfers_leadership() {
+        let (mut room, users) = room_with_users(&["a", "b", "c"]);
+        assert!(matches!(
+            room.transfer_leadership(&users[1], &users[2]),
+            Err(Error::UserNotLeader)
+        ));
+        assert!(matches!(
+            room.transfer_leadership(&users[0], &UserId(99)),
+            Err(Error::UserNotFound)
+        ));
+        room.transfer_leadership(&users[0], &users[2]).unwrap();
+        assert_eq!(room.leader, Some(users[2]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clocks_pause_while_nobody_moves() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            bank_ms: Some(60_000),
+            wakeup_ms: Some(0),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.start_game(&users[0]).unwrap();
+        // The game starts out waiting, but still gets its clock
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(bank_ms(&room, PlayerId(0)), Some(60_000));
+        assert!(room.tick(SystemTime::now()).unwrap());
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        room.user_action(&users[0], &0).unwrap();
+        tokio::time::advance(Duration::from_secs(20)).await;
+        let clocks = room.clock_info(Instant::now()).unwrap();
+        assert!(clocks.iter().all(|clock| !clock.running));
+        assert_eq!(bank_ms(&room, PlayerId(0)), Some(50_000));
+        assert_eq!(bank_ms(&room, PlayerId(1)), Some(60_000));
+
+        assert!(room.tick(SystemTime::now()).unwrap());
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(bank_ms(&room, PlayerId(0)), Some(50_000));
+        assert_eq!(bank_ms(&room, PlayerId(1)), Some(55_000));
+    }
 }
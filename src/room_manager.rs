@@ -1,26 +1,72 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 use serde_json::Value;
-use tokio::sync::{mpsc, oneshot, watch};
-use tracing::{instrument, span, Level};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::task;
+use tokio::time::{sleep_until, Instant};
+use tracing::{instrument, span, warn, Level};
 
+use crate::chat::{ChatChannel, ChatEntry, ChatMessage};
+use crate::clock::PlayerClock;
 use crate::error::Error;
-use crate::game::Game;
+use crate::game::{Game, Outcome};
 use crate::ids::*;
-use crate::protocol::UserInfo;
+use crate::protocol::{
+    LobbyInfo, PatchFormat, SeatingInfo, SeatingPolicy, UndoPolicy, UndoRequestInfo, UserInfo,
+};
+use crate::replay::GameLog;
 use crate::result::Result;
 use crate::room::{JoinInfo, Room};
+use crate::storage::Storage;
 
 type Responder<T> = oneshot::Sender<Result<T>>;
 
+// How many chat messages a connection may fall behind before it starts missing them.
+const CHAT_BACKLOG: usize = 64;
+
+// How many batches of game events a connection may fall behind before it starts missing them.
+const EVENT_BACKLOG: usize = 64;
+type ViewWatch = (
+    watch::Sender<Option<GameUpdate>>,
+    watch::Receiver<Option<GameUpdate>>,
+);
+
+// Everything a single user is shown about the running game.
+#[derive(Clone, Debug)]
+pub struct GameUpdate {
+    pub view: Value,
+    pub legal_actions: Option<Vec<Value>>,
+    // The view replaces the previous one outright, e.g. after an undo, rather than following it
+    pub full: bool,
+    // Increases with every refresh of the room's views
+    pub seq: u64,
+    pub seating: Option<SeatingInfo>,
+}
+
+// Game events for one viewer from a single change to the game.
+#[derive(Clone, Debug)]
+pub struct EventBatch {
+    // The [GameUpdate::seq] of the view these events lead to, which they should be sent just
+    // before. None if the viewer's view did not change.
+    pub view_seq: Option<u64>,
+    pub events: Vec<Value>,
+}
+
 #[derive(Debug)]
 pub struct Subscription {
     pub token: ReconnectToken,
     pub user_id: UserId,
     pub username: String,
-    pub game_view: watch::Receiver<Option<Value>>,
+    pub game_view: watch::Receiver<Option<GameUpdate>>,
+    pub game_events: broadcast::Receiver<EventBatch>,
+    // Chat sent before joining, followed by [chat] for everything after. Both only hold messages
+    // the user may read.
+    pub chat_history: Vec<ChatMessage>,
+    pub chat: broadcast::Receiver<ChatMessage>,
 }
 
 #[derive(Debug)]
@@ -34,6 +80,12 @@ pub enum RoomManagerMessage {
         config: Value,
         resp: Responder<()>,
     },
+    PatchConfig {
+        user_id: UserId,
+        patch: Value,
+        format: PatchFormat,
+        resp: Responder<()>,
+    },
     KickUser {
         user_id: UserId,
         target: UserId,
@@ -45,6 +97,21 @@ pub enum RoomManagerMessage {
         to_user: UserId,
         resp: Responder<()>,
     },
+    SetSpectating {
+        user_id: UserId,
+        spectating: bool,
+        resp: Responder<()>,
+    },
+    SetSeatOrder {
+        user_id: UserId,
+        order: Vec<UserId>,
+        resp: Responder<()>,
+    },
+    SetSeatingPolicy {
+        user_id: UserId,
+        policy: SeatingPolicy,
+        resp: Responder<()>,
+    },
     StartGame {
         user_id: UserId,
         resp: Responder<()>,
@@ -55,176 +122,785 @@ pub enum RoomManagerMessage {
         resp: Responder<()>,
     },
     ResetToLobby {
+        user_id: UserId,
+        reseat: bool,
+        resp: Responder<()>,
+    },
+    Rematch {
+        user_id: UserId,
+        resp: Responder<()>,
+    },
+    SetUndoPolicy {
+        user_id: UserId,
+        policy: UndoPolicy,
+        resp: Responder<()>,
+    },
+    RequestUndo {
+        user_id: UserId,
+        actions: u32,
+        resp: Responder<()>,
+    },
+    RespondUndo {
+        user_id: UserId,
+        approve: bool,
+        resp: Responder<()>,
+    },
+    CancelUndo {
         user_id: UserId,
         resp: Responder<()>,
     },
+    Disconnect {
+        user_id: UserId,
+        resp: Responder<()>,
+    },
+    TransferLeadership {
+        user_id: UserId,
+        target: UserId,
+        resp: Responder<()>,
+    },
+    Chat {
+        user_id: UserId,
+        text: String,
+        channel: ChatChannel,
+        resp: Responder<()>,
+    },
+    MuteUser {
+        user_id: UserId,
+        target: UserId,
+        muted: bool,
+        resp: Responder<()>,
+    },
+    GameLog {
+        resp: Responder<GameLog>,
+    },
+    ReplayView {
+        user_id: UserId,
+        step: u32,
+        resp: Responder<Value>,
+    },
+}
+
+// Which watches need to be refreshed after handling a message or timer.
+#[derive(Default)]
+struct Dirty {
+    users: bool,
+    room: bool,
+    // Every game view needs refreshing
+    game: bool,
+    // Only these viewers' game views need refreshing, as reported by [Game::changed_views]
+    views: Option<HashSet<Option<PlayerId>>>,
+    clock: bool,
+    undo: bool,
+    chat: bool,
+    // Send the game view in full instead of as a diff
+    full_view: bool,
+}
+
+async fn sleep_until_some(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+enum StorageWrite {
+    Save(Value),
+    Delete,
+}
+
+// Applies the writes for one room to [storage], away from the room manager so that slow disk
+// access does not hold up the game. Writes that queue up while one is in progress are coalesced
+// into the latest one.
+async fn write_room(
+    storage: Arc<dyn Storage>,
+    room_id: RoomId,
+    mut writes: mpsc::UnboundedReceiver<StorageWrite>,
+) {
+    while let Some(mut write) = writes.recv().await {
+        while let Ok(next) = writes.try_recv() {
+            write = next;
+        }
+        let storage = storage.clone();
+        let id = room_id.clone();
+        let result = task::spawn_blocking(move || match write {
+            StorageWrite::Save(snapshot) => storage.save(&id, &snapshot),
+            StorageWrite::Delete => storage.delete(&id),
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => warn!("could not write room {}: {}", room_id, err),
+            Err(err) => warn!("writing room {} panicked: {}", room_id, err),
+        }
+    }
+}
+
+// Sending ends of the watches that every connection to the room follows.
+pub struct RoomWatches {
+    pub room: watch::Sender<Option<LobbyInfo>>,
+    pub users: watch::Sender<Vec<UserInfo>>,
+    pub outcome: watch::Sender<Option<Outcome>>,
+    pub clock: watch::Sender<Option<Vec<PlayerClock>>>,
+    pub undo: watch::Sender<Option<UndoRequestInfo>>,
 }
 
 pub struct RoomManager<T: Game + Send + Sync + 'static> {
+    room_id: RoomId,
     room: Room<T>,
     message_rx: mpsc::Receiver<RoomManagerMessage>,
-    room_tx: watch::Sender<Option<Value>>,
-    users_tx: watch::Sender<Vec<UserInfo>>,
-    view_watches: HashMap<UserId, (watch::Sender<Option<Value>>, watch::Receiver<Option<Value>>)>,
+    watches: RoomWatches,
+    view_watches: HashMap<UserId, ViewWatch>,
+    event_txs: HashMap<UserId, broadcast::Sender<EventBatch>>,
+    view_seq: u64,
+    chat_txs: HashMap<UserId, broadcast::Sender<ChatMessage>>,
+    // Game wakeup that failed or was still due after it fired, so it isn't retried in a loop
+    stale_wakeup: Option<SystemTime>,
+    // Sends writes to the room's storage task, if the room is kept in storage
+    storage_tx: Option<mpsc::UnboundedSender<StorageWrite>>,
+    // When the changes made since the last snapshot get saved
+    save_deadline: Option<Instant>,
 }
 
 impl<T: Game + Send + Sync + 'static> RoomManager<T> {
     pub fn new(
+        room_id: RoomId,
+        room: Room<T>,
         message_rx: mpsc::Receiver<RoomManagerMessage>,
-        room_tx: watch::Sender<Option<Value>>,
-        users_tx: watch::Sender<Vec<UserInfo>>,
+        watches: RoomWatches,
     ) -> Self {
+        let storage_tx = room.options().storage.clone().map(|storage| {
+            let (storage_tx, writes) = mpsc::unbounded_channel();
+            tokio::spawn(write_room(storage, room_id.clone(), writes));
+            storage_tx
+        });
         let s = Self {
-            room: Room::<T>::new(),
+            room_id,
+            room,
             message_rx,
-            room_tx,
-            users_tx,
+            watches,
             view_watches: HashMap::new(),
+            event_txs: HashMap::new(),
+            view_seq: 0,
+            chat_txs: HashMap::new(),
+            stale_wakeup: None,
+            storage_tx,
+            save_deadline: None,
         };
         s.update_room();
+        s.update_users();
+        s.update_outcome();
+        s.update_undo();
+        s.update_clock();
         s
     }
 
+    // Snapshots the room at most once per [save_delay], so that busy rooms do not pay for
+    // serializing the whole room after every message.
+    fn schedule_save(&mut self) {
+        if self.storage_tx.is_some() && self.save_deadline.is_none() {
+            self.save_deadline = Some(Instant::now() + self.room.options().save_delay);
+        }
+    }
+
+    fn save(&mut self) {
+        self.save_deadline = None;
+        if let Some(storage_tx) = &self.storage_tx {
+            // The storage task only stops after this manager drops the sender
+            let _ = storage_tx.send(StorageWrite::Save(self.room.snapshot()));
+        }
+    }
+
+    fn delete(&mut self) {
+        self.save_deadline = None;
+        if let Some(storage_tx) = &self.storage_tx {
+            let _ = storage_tx.send(StorageWrite::Delete);
+        }
+    }
+
+    // Passes [entry] on to the connections of everyone who may read it.
+    fn send_chat(&self, entry: ChatEntry) {
+        for (user_id, chat_tx) in self.chat_txs.iter() {
+            if entry.visible_to(user_id) {
+                // Fails only if the user has no open connection
+                let _ = chat_tx.send(entry.message.clone());
+            }
+        }
+    }
+
     fn update_users(&self) {
         // TODO: error handling
-        self.users_tx.send(self.room.user_info()).unwrap()
+        self.watches.users.send(self.room.user_info()).unwrap()
     }
 
     fn update_room(&self) {
+        let lobby_info = self.room.lobby_info();
+        self.watches.room.send_if_modified(|current| {
+            if *current != lobby_info {
+                *current = lobby_info;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    fn update_outcome(&self) {
+        let outcome = self.room.outcome().cloned();
+        self.watches.outcome.send_if_modified(|current| {
+            if *current != outcome {
+                *current = outcome;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    fn update_undo(&self) {
+        let request = self.room.undo_request();
+        self.watches.undo.send_if_modified(|current| {
+            if *current != request {
+                *current = request;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    fn update_clock(&self) {
         // TODO: error handling
-        self.room_tx.send(self.room.lobby_info()).unwrap()
-    }
-
-    #[instrument(skip(self))]
-    fn update_game(&self) {
-        let Self {
-            room, view_watches, ..
-        } = &self;
-        for (user_id, (tx, _rx)) in view_watches.iter() {
-            let new_view = {
-                let _span = span!(Level::INFO, "creating and serializing view").entered();
-                match room.user_view(user_id) {
-                    Ok(view) => Some(serde_json::to_value(view).unwrap()),
-                    Err(_) => None,
+        self.watches
+            .clock
+            .send(self.room.clock_info(Instant::now()))
+            .unwrap()
+    }
+
+    // Records a change to the game state that may have ended a turn or the game.
+    fn game_changed(&mut self, active_players: Option<Vec<PlayerId>>, dirty: &mut Dirty) {
+        // Finishing the game changes everyone's legal actions
+        match self.room.changed_views() {
+            Some(views) if self.room.outcome().is_none() => {
+                dirty.views.get_or_insert_with(HashSet::new).extend(views)
+            }
+            _ => dirty.game = true,
+        }
+        dirty.clock = true;
+        dirty.undo = true;
+        if self.room.active_players() != active_players {
+            dirty.users = true;
+        }
+    }
+
+    fn legal_actions(&self, player: Option<PlayerId>) -> Option<Vec<Value>> {
+        self.room
+            .player_legal_actions(player)
+            .ok()
+            .flatten()
+            .map(|actions| {
+                actions
+                    .iter()
+                    .map(|action| serde_json::to_value(action).unwrap())
+                    .collect()
+            })
+    }
+
+    fn game_update(&self, player: Option<PlayerId>, full: bool) -> Option<GameUpdate> {
+        let _span = span!(Level::INFO, "creating and serializing view").entered();
+        let view = self.room.player_view(player).ok()?;
+        Some(GameUpdate {
+            view: serde_json::to_value(view).unwrap(),
+            legal_actions: self.legal_actions(player),
+            full,
+            seq: self.view_seq,
+            seating: self.room.seating_info(),
+        })
+    }
+
+    // Refreshes the views of connected users, or only of [views] if given, and hands out [events].
+    // Each distinct view is computed once and shared, so all spectators cost as much as one. Players
+    // whose view is left alone still get their legal actions refreshed.
+    #[instrument(skip(self, events))]
+    fn update_game(
+        &mut self,
+        views: Option<&HashSet<Option<PlayerId>>>,
+        full: bool,
+        events: Vec<T::Event>,
+    ) {
+        self.view_seq += 1;
+        let mut cache = HashMap::new();
+        let mut event_cache = HashMap::new();
+        for (user_id, (tx, _rx)) in self.view_watches.iter() {
+            // Users get a fresh view when they reconnect
+            if !self.room.user_connected(user_id) {
+                continue;
+            }
+            let player = self.room.user_player(user_id);
+            let refresh = views.is_none_or(|views| views.contains(&player));
+            if !events.is_empty() {
+                let viewer_events = event_cache
+                    .entry(player)
+                    .or_insert_with(|| self.room.viewer_events(&events, player))
+                    .clone();
+                if let (false, Some(events_tx)) =
+                    (viewer_events.is_empty(), self.event_txs.get(user_id))
+                {
+                    // Fails only if the user has no open connection
+                    let _ = events_tx.send(EventBatch {
+                        view_seq: refresh.then_some(self.view_seq),
+                        events: viewer_events,
+                    });
+                }
+            }
+            if !refresh {
+                // Legal actions can change without the view, e.g. when the turn passes
+                if player.is_some() && T::PUBLISH_LEGAL_ACTIONS {
+                    let legal_actions = self.legal_actions(player);
+                    tx.send_if_modified(|update| match update {
+                        Some(update) if update.legal_actions != legal_actions => {
+                            update.legal_actions = legal_actions;
+                            true
+                        }
+                        _ => false,
+                    });
                 }
-            };
+                continue;
+            }
+            let update = cache
+                .entry(player)
+                .or_insert_with(|| self.game_update(player, full))
+                .clone();
             // TODO: error handling
-            tx.send(new_view).unwrap()
+            tx.send(update).unwrap()
         }
     }
 
-    pub async fn run(&mut self) {
-        while let Some(message) = self.message_rx.recv().await {
-            let mut users_dirty = false;
-            let mut room_dirty = false;
-            let mut game_dirty = false;
-            match message {
-                RoomManagerMessage::JoinRoom { join_info, resp } => {
-                    let _ = match self.room.join_room(join_info) {
-                        Err(err) => resp.send(Err(err)),
-                        Ok(user_data) => {
-                            let (_tx, rx) = self
-                                .view_watches
-                                .entry(user_data.id.clone())
-                                .or_insert_with(|| watch::channel(None));
-                            users_dirty = true;
-                            game_dirty = true;
-                            resp.send(Ok(Subscription {
-                                token: user_data.token.clone(),
-                                user_id: user_data.id.clone(),
-                                username: user_data.username.clone(),
-                                game_view: rx.clone(),
-                            }))
+    fn game_started(&mut self, dirty: &mut Dirty) {
+        dirty.users = true;
+        dirty.room = true;
+        dirty.game = true;
+        dirty.clock = true;
+        dirty.undo = true;
+    }
+
+    // [result] says whether an undo request went through and rolled the game back.
+    fn undo_resolved(
+        &mut self,
+        result: &Result<bool>,
+        active_players: Option<Vec<PlayerId>>,
+        dirty: &mut Dirty,
+    ) {
+        match result {
+            Ok(true) => {
+                self.game_changed(active_players, dirty);
+                dirty.game = true;
+                dirty.full_view = true;
+            }
+            Ok(false) => dirty.undo = true,
+            Err(_) => (),
+        }
+    }
+
+    fn handle_message(&mut self, message: RoomManagerMessage, dirty: &mut Dirty) {
+        match message {
+            RoomManagerMessage::JoinRoom { join_info, resp } => {
+                let _ = match self.room.join_room(join_info) {
+                    Err(err) => resp.send(Err(err)),
+                    Ok(user_data) => {
+                        let (_tx, rx) = self
+                            .view_watches
+                            .entry(user_data.id)
+                            .or_insert_with(|| watch::channel(None));
+                        let game_events = self
+                            .event_txs
+                            .entry(user_data.id)
+                            .or_insert_with(|| broadcast::channel(EVENT_BACKLOG).0)
+                            .subscribe();
+                        let chat = self
+                            .chat_txs
+                            .entry(user_data.id)
+                            .or_insert_with(|| broadcast::channel(CHAT_BACKLOG).0)
+                            .subscribe();
+                        let mut subscription = Subscription {
+                            token: user_data.token.clone(),
+                            user_id: user_data.id,
+                            username: user_data.username.clone(),
+                            game_view: rx.clone(),
+                            game_events,
+                            chat_history: Vec::new(),
+                            chat,
+                        };
+                        subscription.chat_history = self.room.chat_history(&subscription.user_id);
+                        dirty.users = true;
+                        dirty.game = true;
+                        resp.send(Ok(subscription))
+                    }
+                };
+            }
+            RoomManagerMessage::UpdateConfig {
+                user_id,
+                config,
+                resp,
+            } => {
+                let result = match serde_json::from_value(config) {
+                    Ok(config) => {
+                        let result = self.room.update_config(&user_id, config);
+                        if result.is_ok() {
+                            dirty.room = true;
                         }
-                    };
+                        result
+                    }
+                    Err(_) => Err(Error::ParseFailure),
+                };
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::PatchConfig {
+                user_id,
+                patch,
+                format,
+                resp,
+            } => {
+                let result = self.room.patch_config(&user_id, patch, format);
+                if result.is_ok() {
+                    dirty.room = true;
                 }
-                RoomManagerMessage::UpdateConfig {
-                    user_id,
-                    config,
-                    resp,
-                } => {
-                    let result = match serde_json::from_value(config) {
-                        Ok(config) => {
-                            let result = self.room.update_config(&user_id, config);
-                            if result.is_ok() {
-                                room_dirty = true;
-                            }
-                            result
-                        }
-                        Err(_) => Err(Error::ParseFailure),
-                    };
-                    let _ = resp.send(result);
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::KickUser {
+                user_id,
+                target,
+                resp,
+            } => {
+                let result = self.room.kick_user(&user_id, &target);
+                if result.is_ok() {
+                    dirty.users = true;
                 }
-                RoomManagerMessage::KickUser {
-                    user_id,
-                    target,
-                    resp,
-                } => {
-                    let result = self.room.kick_user(&user_id, &target);
-                    if result.is_ok() {
-                        users_dirty = true;
-                    }
-                    let _ = resp.send(result);
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::ReassignPlayer {
+                user_id,
+                from_user,
+                to_user,
+                resp,
+            } => {
+                let result = self.room.reassign_player(&user_id, &from_user, &to_user);
+                if result.is_ok() {
+                    dirty.users = true;
+                    dirty.game = true;
                 }
-                RoomManagerMessage::ReassignPlayer {
-                    user_id,
-                    from_user,
-                    to_user,
-                    resp,
-                } => {
-                    let result = self.room.reassign_player(&user_id, &from_user, &to_user);
-                    if result.is_ok() {
-                        users_dirty = true;
-                        game_dirty = true;
-                    }
-                    let _ = resp.send(result);
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::SetSpectating {
+                user_id,
+                spectating,
+                resp,
+            } => {
+                let result = self.room.set_spectating(&user_id, spectating);
+                if result.is_ok() {
+                    dirty.users = true;
                 }
-                RoomManagerMessage::StartGame { user_id, resp } => {
-                    let result = self.room.start_game(&user_id);
-                    if result.is_ok() {
-                        users_dirty = true;
-                        room_dirty = true;
-                        game_dirty = true;
-                    }
-                    let _ = resp.send(result);
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::SetSeatOrder {
+                user_id,
+                order,
+                resp,
+            } => {
+                let result = self.room.set_seat_order(&user_id, order);
+                if result.is_ok() {
+                    dirty.users = true;
                 }
-                RoomManagerMessage::ResetToLobby { user_id, resp } => {
-                    let result = self.room.reset_to_lobby(&user_id);
-                    if result.is_ok() {
-                        users_dirty = true;
-                        room_dirty = true;
-                        game_dirty = true;
-                    }
-                    let _ = resp.send(result);
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::SetSeatingPolicy {
+                user_id,
+                policy,
+                resp,
+            } => {
+                let result = self.room.set_seating_policy(&user_id, policy);
+                if result.is_ok() {
+                    dirty.users = true;
+                    dirty.room = true;
+                }
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::StartGame { user_id, resp } => {
+                let result = self.room.start_game(&user_id);
+                if result.is_ok() {
+                    self.game_started(dirty);
+                }
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::Rematch { user_id, resp } => {
+                let result = self.room.rematch(&user_id);
+                if result.is_ok() {
+                    self.game_started(dirty);
+                    dirty.full_view = true;
+                }
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::ResetToLobby {
+                user_id,
+                reseat,
+                resp,
+            } => {
+                let result = self.room.reset_to_lobby(&user_id, reseat);
+                if result.is_ok() {
+                    dirty.users = true;
+                    dirty.room = true;
+                    dirty.game = true;
+                    dirty.clock = true;
+                    dirty.undo = true;
                 }
-                RoomManagerMessage::DoAction {
-                    user_id,
-                    action,
-                    resp,
-                } => {
-                    let result = match serde_json::from_value(action) {
-                        Ok(action) => {
-                            let result = self.room.user_action(&user_id, &action);
-                            if result.is_ok() {
-                                game_dirty = true;
-                            }
-                            result
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::SetUndoPolicy {
+                user_id,
+                policy,
+                resp,
+            } => {
+                let result = self.room.set_undo_policy(&user_id, policy);
+                if result.is_ok() {
+                    dirty.room = true;
+                }
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::RequestUndo {
+                user_id,
+                actions,
+                resp,
+            } => {
+                let active_players = self.room.active_players();
+                let result = self.room.request_undo(&user_id, actions);
+                self.undo_resolved(&result, active_players, dirty);
+                let _ = resp.send(result.map(|_| ()));
+            }
+            RoomManagerMessage::RespondUndo {
+                user_id,
+                approve,
+                resp,
+            } => {
+                let active_players = self.room.active_players();
+                let result = self.room.respond_undo(&user_id, approve);
+                self.undo_resolved(&result, active_players, dirty);
+                let _ = resp.send(result.map(|_| ()));
+            }
+            RoomManagerMessage::CancelUndo { user_id, resp } => {
+                let result = self.room.cancel_undo(&user_id);
+                if result.is_ok() {
+                    dirty.undo = true;
+                }
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::DoAction {
+                user_id,
+                action,
+                resp,
+            } => {
+                let result = match serde_json::from_value(action) {
+                    Ok(action) => {
+                        let active_players = self.room.active_players();
+                        let result = self.room.user_action(&user_id, &action);
+                        if result.is_ok() {
+                            self.game_changed(active_players, dirty);
                         }
-                        Err(_) => Err(Error::ParseFailure),
-                    };
-                    let _ = resp.send(result);
+                        result
+                    }
+                    Err(_) => Err(Error::ParseFailure),
+                };
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::TransferLeadership {
+                user_id,
+                target,
+                resp,
+            } => {
+                let result = self.room.transfer_leadership(&user_id, &target);
+                if result.is_ok() {
+                    dirty.users = true;
+                }
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::Chat {
+                user_id,
+                text,
+                channel,
+                resp,
+            } => {
+                let result = self.room.chat(&user_id, text, channel).map(|entry| {
+                    self.send_chat(entry);
+                    dirty.chat = true;
+                });
+                let _ = resp.send(result);
+            }
+            RoomManagerMessage::MuteUser {
+                user_id,
+                target,
+                muted,
+                resp,
+            } => {
+                let result = self.room.mute_user(&user_id, &target, muted);
+                if result.is_ok() {
+                    dirty.users = true;
                 }
+                let _ = resp.send(result);
             }
+            RoomManagerMessage::GameLog { resp } => {
+                let _ = resp.send(self.room.game_log().cloned());
+            }
+            RoomManagerMessage::ReplayView {
+                user_id,
+                step,
+                resp,
+            } => {
+                let _ = resp.send(self.room.replay_view(&user_id, step as usize));
+            }
+            RoomManagerMessage::Disconnect { user_id, resp } => {
+                let result = self.room.disconnect(&user_id, Instant::now());
+                if result.is_ok() {
+                    dirty.users = true;
+                }
+                let _ = resp.send(result);
+            }
+        }
+    }
 
-            if users_dirty {
+    fn handle_timers(&mut self, now: Instant, dirty: &mut Dirty) {
+        if self.room.expire_disconnected(now) {
+            dirty.users = true;
+        }
+        if self.room.hand_over_leadership(now) {
+            dirty.users = true;
+        }
+        if self.room.expire_undo(now) {
+            dirty.undo = true;
+        }
+        let expired = self.room.expired_players(now);
+        if !expired.is_empty() {
+            let active_players = self.room.active_players();
+            for player in expired {
+                // An earlier timeout may have ended the game or moved it on
+                if !self.room.player_on_clock(player) {
+                    continue;
+                }
+                // The clock reports each player once per turn, so a failure isn't retried
+                if let Err(err) = self.room.player_timeout(player) {
+                    warn!(
+                        "timeout for {:?} in room {} failed: {}",
+                        player, self.room_id, err
+                    );
+                }
+            }
+            self.game_changed(active_players, dirty);
+        }
+        let wakeup = self.room.next_game_wakeup();
+        if wakeup.is_none() || wakeup == self.stale_wakeup {
+            return;
+        }
+        let active_players = self.room.active_players();
+        let system_now = SystemTime::now();
+        match self.room.tick(system_now) {
+            Ok(true) => self.game_changed(active_players, dirty),
+            Ok(false) => return,
+            Err(err) => warn!("tick in room {} failed: {}", self.room_id, err),
+        }
+        // Waking up again for the same time would spin, so wait until the game moves on
+        let next = self.room.next_game_wakeup();
+        if next.is_some_and(|next| next <= system_now) {
+            warn!(
+                "room {} did not advance its wakeup; ignoring it",
+                self.room_id
+            );
+            self.stale_wakeup = next;
+        }
+    }
+
+    fn next_game_wakeup(&self) -> Option<Instant> {
+        let wakeup = self
+            .room
+            .next_game_wakeup()
+            .filter(|wakeup| Some(*wakeup) != self.stale_wakeup)?;
+        let delay = wakeup.duration_since(SystemTime::now()).unwrap_or_default();
+        Some(Instant::now() + delay)
+    }
+
+    fn next_wakeup(&self) -> Option<Instant> {
+        [
+            self.room.next_expiry(),
+            self.room.next_leader_handover(),
+            self.room.undo_deadline(),
+            self.room.idle_deadline(),
+            self.room.next_clock_deadline(),
+            self.next_game_wakeup(),
+            self.save_deadline,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    // Runs until the message channel closes or the room has been idle for too long.
+    pub async fn run(&mut self) {
+        loop {
+            let mut dirty = Dirty::default();
+            let wakeup = self.next_wakeup();
+            tokio::select! {
+                message = self.message_rx.recv() => match message {
+                    Some(message) => self.handle_message(message, &mut dirty),
+                    None => break,
+                },
+                _ = sleep_until_some(wakeup) => {
+                    let now = Instant::now();
+                    if self.room.idle_deadline().is_some_and(|deadline| deadline <= now) {
+                        self.delete();
+                        break;
+                    }
+                    self.handle_timers(now, &mut dirty)
+                },
+            }
+
+            if dirty.users {
                 self.update_users()
             }
-            if game_dirty {
-                self.update_game()
+            if dirty.game || dirty.views.is_some() {
+                let views = if dirty.game {
+                    None
+                } else {
+                    dirty.views.as_ref()
+                };
+                let events = self.room.take_events();
+                self.update_game(views, dirty.full_view, events);
+                self.update_outcome()
+            }
+            if dirty.undo {
+                self.update_undo()
             }
-            if room_dirty {
+            // Seating changes can change whether the game can start
+            if dirty.room || dirty.users {
                 self.update_room()
             }
+            if dirty.clock {
+                self.update_clock()
+            }
+            if dirty.users
+                || dirty.room
+                || dirty.game
+                || dirty.views.is_some()
+                || dirty.undo
+                || dirty.chat
+            {
+                self.schedule_save()
+            }
+            if self
+                .save_deadline
+                .is_some_and(|deadline| deadline <= Instant::now())
+            {
+                self.save()
+            }
+        }
+        // Don't lose changes still waiting for their save when the server drops the room
+        if self.save_deadline.is_some() {
+            self.save()
         }
     }
 }
@@ -232,26 +908,54 @@ impl<T: Game + Send + Sync + 'static> RoomManager<T> {
 #[derive(Clone)]
 pub struct RoomManagerHandle<T: Game> {
     tx: mpsc::Sender<RoomManagerMessage>,
-    room_watch: watch::Receiver<Option<Value>>,
+    room_watch: watch::Receiver<Option<LobbyInfo>>,
     users_watch: watch::Receiver<Vec<UserInfo>>,
+    outcome_watch: watch::Receiver<Option<Outcome>>,
+    clock_watch: watch::Receiver<Option<Vec<PlayerClock>>>,
+    undo_watch: watch::Receiver<Option<UndoRequestInfo>>,
     game_type: PhantomData<T>,
 }
 
 impl<T: Game> RoomManagerHandle<T> {
-    pub fn new() -> Self {
+    // [on_close] runs once the room manager has shut down, and should forget this handle.
+    pub fn new<F>(room_id: RoomId, room: Room<T>, on_close: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let (mut room_manager, handle) = Self::unstarted(room_id, room);
+        tokio::spawn(async move {
+            room_manager.run().await;
+            on_close();
+        });
+        handle
+    }
+
+    // A manager for [room] that has yet to be run, along with its handle.
+    fn unstarted(room_id: RoomId, room: Room<T>) -> (RoomManager<T>, Self) {
         let (tx, message_rx) = mpsc::channel(32);
         let (room_tx, room_watch) = watch::channel(None);
         let (users_tx, users_watch) = watch::channel(Vec::new());
-        tokio::spawn(async move {
-            let mut room_manager = RoomManager::<T>::new(message_rx, room_tx, users_tx);
-            room_manager.run().await
-        });
-        Self {
+        let (outcome_tx, outcome_watch) = watch::channel(None);
+        let (clock_tx, clock_watch) = watch::channel(None);
+        let (undo_tx, undo_watch) = watch::channel(None);
+        let watches = RoomWatches {
+            room: room_tx,
+            users: users_tx,
+            outcome: outcome_tx,
+            clock: clock_tx,
+            undo: undo_tx,
+        };
+        let room_manager = RoomManager::new(room_id, room, message_rx, watches);
+        let handle = Self {
             tx,
             room_watch,
             users_watch,
+            outcome_watch,
+            clock_watch,
+            undo_watch,
             game_type: PhantomData,
-        }
+        };
+        (room_manager, handle)
     }
 
     async fn send_message<R, F>(&self, message: F) -> Result<R>
@@ -259,14 +963,13 @@ impl<T: Game> RoomManagerHandle<T> {
         F: FnOnce(Responder<R>) -> RoomManagerMessage,
     {
         let (tx, rx) = oneshot::channel();
-        let result = self.tx.send(message(tx)).await;
-        match result {
-            Ok(()) => (),
-            Err(err) => return Err(Error::TokioError(err.to_string())),
-        };
+        // Both channels only fail once the room manager has shut down
+        if self.tx.send(message(tx)).await.is_err() {
+            return Err(Error::RoomClosed);
+        }
         match rx.await {
             Ok(res) => res,
-            Err(err) => Err(Error::TokioError(err.to_string())),
+            Err(_) => Err(Error::RoomClosed),
         }
     }
 
@@ -295,6 +998,21 @@ impl<T: Game> RoomManagerHandle<T> {
         .await
     }
 
+    pub async fn patch_config(
+        &self,
+        user_id: UserId,
+        patch: Value,
+        format: PatchFormat,
+    ) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::PatchConfig {
+            user_id,
+            patch,
+            format,
+            resp,
+        })
+        .await
+    }
+
     pub async fn kick_user(&self, user_id: UserId, target: UserId) -> Result<()> {
         self.send_message(|resp| RoomManagerMessage::KickUser {
             user_id,
@@ -319,13 +1037,49 @@ impl<T: Game> RoomManagerHandle<T> {
         .await
     }
 
+    pub async fn set_spectating(&self, user_id: UserId, spectating: bool) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::SetSpectating {
+            user_id,
+            spectating,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn set_seat_order(&self, user_id: UserId, order: Vec<UserId>) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::SetSeatOrder {
+            user_id,
+            order,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn set_seating_policy(&self, user_id: UserId, policy: SeatingPolicy) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::SetSeatingPolicy {
+            user_id,
+            policy,
+            resp,
+        })
+        .await
+    }
+
     pub async fn start_game(&self, user_id: UserId) -> Result<()> {
         self.send_message(|resp| RoomManagerMessage::StartGame { user_id, resp })
             .await
     }
 
-    pub async fn reset_to_lobby(&self, user_id: UserId) -> Result<()> {
-        self.send_message(|resp| RoomManagerMessage::ResetToLobby { user_id, resp })
+    pub async fn reset_to_lobby(&self, user_id: UserId, reseat: bool) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::ResetToLobby {
+            user_id,
+            reseat,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn rematch(&self, user_id: UserId) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::Rematch { user_id, resp })
             .await
     }
 
@@ -338,11 +1092,467 @@ impl<T: Game> RoomManagerHandle<T> {
         .await
     }
 
-    pub fn watch_room(&self) -> watch::Receiver<Option<Value>> {
+    pub async fn transfer_leadership(&self, user_id: UserId, target: UserId) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::TransferLeadership {
+            user_id,
+            target,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn set_undo_policy(&self, user_id: UserId, policy: UndoPolicy) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::SetUndoPolicy {
+            user_id,
+            policy,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn request_undo(&self, user_id: UserId, actions: u32) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::RequestUndo {
+            user_id,
+            actions,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn respond_undo(&self, user_id: UserId, approve: bool) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::RespondUndo {
+            user_id,
+            approve,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn cancel_undo(&self, user_id: UserId) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::CancelUndo { user_id, resp })
+            .await
+    }
+
+    pub async fn chat(&self, user_id: UserId, text: String, channel: ChatChannel) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::Chat {
+            user_id,
+            text,
+            channel,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn mute_user(&self, user_id: UserId, target: UserId, muted: bool) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::MuteUser {
+            user_id,
+            target,
+            muted,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn game_log(&self) -> Result<GameLog> {
+        self.send_message(|resp| RoomManagerMessage::GameLog { resp })
+            .await
+    }
+
+    pub async fn replay_view(&self, user_id: UserId, step: u32) -> Result<Value> {
+        self.send_message(|resp| RoomManagerMessage::ReplayView {
+            user_id,
+            step,
+            resp,
+        })
+        .await
+    }
+
+    pub async fn disconnect(&self, user_id: UserId) -> Result<()> {
+        self.send_message(|resp| RoomManagerMessage::Disconnect { user_id, resp })
+            .await
+    }
+
+    pub fn watch_room(&self) -> watch::Receiver<Option<LobbyInfo>> {
         self.room_watch.clone()
     }
 
     pub fn watch_users(&self) -> watch::Receiver<Vec<UserInfo>> {
         self.users_watch.clone()
     }
+
+    pub fn watch_outcome(&self) -> watch::Receiver<Option<Outcome>> {
+        self.outcome_watch.clone()
+    }
+
+    pub fn watch_clock(&self) -> watch::Receiver<Option<Vec<PlayerClock>>> {
+        self.clock_watch.clone()
+    }
+
+    pub fn watch_undo(&self) -> watch::Receiver<Option<UndoRequestInfo>> {
+        self.undo_watch.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+    use tokio::time::sleep;
+
+    use super::*;
+    use crate::options::RoomOptions;
+    use crate::test_game::{room_with_users, TestConfig, TestGame};
+
+    #[tokio::test]
+    async fn game_log_requests_only_reveal_seeds_after_the_game() {
+        let (mut room, users) = room_with_users(&["a", "b"]);
+        let config = TestConfig {
+            target: Some(1),
+            ..TestConfig::default()
+        };
+        room.update_config(&users[0], config).unwrap();
+        room.set_seating_policy(&users[0], SeatingPolicy::Random)
+            .unwrap();
+        room.start_game(&users[0]).unwrap();
+        let handle = RoomManagerHandle::new(RoomId::new(), room, || ());
+
+        assert!(matches!(
+            handle.game_log().await,
+            Err(Error::GameNotFinished)
+        ));
+        assert!(matches!(
+            handle.replay_view(users[0], 0).await,
+            Err(Error::GameNotFinished)
+        ));
+
+        // The manager has published its users by the time it answered
+        let user_info = handle.watch_users().borrow().clone();
+        let mover = user_info.iter().find(|user| user.active).unwrap().id;
+        handle.do_action(mover, json!(1)).await.unwrap();
+        let game_log = handle.game_log().await.unwrap();
+        assert!(game_log.seating_seed.is_some());
+    }
+
+    #[tokio::test]
+    async fn chat_only_reaches_its_recipients() {
+        let handle = RoomManagerHandle::new(RoomId::new(), Room::<TestGame>::default(), || ());
+        let mut users = Vec::new();
+        let mut chats = Vec::new();
+        for name in ["a", "b", "c"] {
+            let subscription = handle.join_room(name.to_string()).await.unwrap();
+            users.push(subscription.user_id);
+            chats.push(subscription.chat);
+        }
+        handle.set_spectating(users[2], true).await.unwrap();
+        handle.start_game(users[0]).await.unwrap();
+
+        handle
+            .chat(users[2], "psst".to_string(), ChatChannel::Room)
+            .await
+            .unwrap();
+        handle
+            .chat(
+                users[0],
+                "hi".to_string(),
+                ChatChannel::Whisper { to: users[1] },
+            )
+            .await
+            .unwrap();
+        let received: Vec<Vec<String>> = chats
+            .iter_mut()
+            .map(|chat| {
+                std::iter::from_fn(|| chat.try_recv().ok())
+                    .map(|message| message.text)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(received, vec![vec!["hi"], vec!["hi"], vec!["psst"]]);
+    }
+
+    // A manager running [config]'s game between [players], seated in order, with [spectators]
+    // joining once it has started. Subscriptions follow the order of the names.
+    async fn running_game(
+        config: TestConfig,
+        players: &[&str],
+        spectators: &[&str],
+    ) -> (RoomManagerHandle<TestGame>, Vec<Subscription>) {
+        let mut room = Room::<TestGame>::default();
+        let joined: Vec<(UserId, ReconnectToken)> = players
+            .iter()
+            .map(|name| {
+                let data = room
+                    .join_room(JoinInfo::Username(name.to_string()))
+                    .unwrap();
+                (data.id, data.token.clone())
+            })
+            .collect();
+        room.update_config(&joined[0].0, config).unwrap();
+        room.start_game(&joined[0].0).unwrap();
+        // Players connect again through the manager to get their views
+        let now = Instant::now();
+        for (user, _) in &joined {
+            room.disconnect(user, now).unwrap();
+        }
+        let handle = RoomManagerHandle::new(RoomId::new(), room, || ());
+        let mut subscriptions = Vec::new();
+        for (_, token) in joined {
+            subscriptions.push(handle.rejoin_room(token).await.unwrap());
+        }
+        for name in spectators {
+            subscriptions.push(handle.join_room(name.to_string()).await.unwrap());
+        }
+        (handle, subscriptions)
+    }
+
+    fn total(subscription: &Subscription) -> Value {
+        subscription.game_view.borrow().as_ref().unwrap().view["total"].clone()
+    }
+
+    #[tokio::test]
+    async fn spectators_share_one_view() {
+        let config = TestConfig::default();
+        let views = config.views.clone();
+        let (handle, subscriptions) = running_game(config, &["a", "b"], &["c", "d", "e"]).await;
+        let before = views.load(std::sync::atomic::Ordering::SeqCst);
+        handle
+            .do_action(subscriptions[0].user_id, json!(2))
+            .await
+            .unwrap();
+        // Views are refreshed before the manager answers the next message
+        let _ = handle.game_log().await;
+        // One view for each player and one for all spectators
+        assert_eq!(views.load(std::sync::atomic::Ordering::SeqCst) - before, 3);
+        assert!(subscriptions
+            .iter()
+            .all(|subscription| total(subscription) == json!(2)));
+    }
+
+    #[tokio::test]
+    async fn disconnected_users_get_a_fresh_view_when_they_rejoin() {
+        let config = TestConfig::default();
+        let views = config.views.clone();
+        let (handle, mut subscriptions) = running_game(config, &["a", "b"], &["c"]).await;
+        let mut spectator = subscriptions.pop().unwrap();
+        handle.disconnect(spectator.user_id).await.unwrap();
+        spectator.game_view.borrow_and_update();
+        let before = views.load(std::sync::atomic::Ordering::SeqCst);
+        handle
+            .do_action(subscriptions[0].user_id, json!(2))
+            .await
+            .unwrap();
+        let _ = handle.game_log().await;
+        assert!(!spectator.game_view.has_changed().unwrap());
+        assert_eq!(views.load(std::sync::atomic::Ordering::SeqCst) - before, 2);
+
+        let rejoined = handle.rejoin_room(spectator.token).await.unwrap();
+        let _ = handle.game_log().await;
+        assert_eq!(total(&rejoined), json!(2));
+    }
+
+    #[tokio::test]
+    async fn changed_views_refresh_only_the_listed_viewers() {
+        let config = TestConfig {
+            changed_views: Some(vec![Some(PlayerId(0))]),
+            ..TestConfig::default()
+        };
+        let (handle, mut subscriptions) = running_game(config, &["a", "b"], &["c"]).await;
+        for subscription in subscriptions.iter_mut() {
+            subscription.game_view.borrow_and_update();
+        }
+        handle
+            .do_action(subscriptions[0].user_id, json!(2))
+            .await
+            .unwrap();
+        let _ = handle.game_log().await;
+        assert_eq!(total(&subscriptions[0]), json!(2));
+        assert!(!subscriptions[2].game_view.has_changed().unwrap());
+
+        // The next player keeps their view but learns that it is their turn
+        let update = subscriptions[1].game_view.borrow().clone().unwrap();
+        assert_eq!(update.view["total"], json!(0));
+        assert_eq!(
+            update.legal_actions,
+            Some(vec![json!(1), json!(2), json!(3)])
+        );
+    }
+
+    // Keeps snapshots in memory so tests can see what was written.
+    #[derive(Debug, Default)]
+    struct MemoryStorage {
+        rooms: std::sync::Mutex<HashMap<RoomId, Value>>,
+        saves: std::sync::atomic::AtomicUsize,
+    }
+
+    impl Storage for MemoryStorage {
+        fn save(&self, room_id: &RoomId, snapshot: &Value) -> Result<()> {
+            let mut rooms = self.rooms.lock().unwrap();
+            rooms.insert(room_id.clone(), snapshot.clone());
+            self.saves.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn delete(&self, room_id: &RoomId) -> Result<()> {
+            self.rooms.lock().unwrap().remove(room_id);
+            Ok(())
+        }
+
+        fn load_all(&self) -> Result<Vec<(RoomId, Value)>> {
+            Ok(self.rooms.lock().unwrap().clone().into_iter().collect())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_rooms_shut_down_and_leave_storage() {
+        let storage = Arc::new(MemoryStorage::default());
+        let options = RoomOptions {
+            storage: Some(storage.clone()),
+            ..RoomOptions::default()
+        };
+        let idle_ttl = options.idle_ttl;
+        let save_delay = options.save_delay;
+        let room_id = RoomId::new();
+        let (closed_tx, closed) = oneshot::channel();
+        let handle =
+            RoomManagerHandle::new(room_id.clone(), Room::<TestGame>::new(options), move || {
+                let _ = closed_tx.send(());
+            });
+        let user_id = handle.join_room("a".to_string()).await.unwrap().user_id;
+        handle.disconnect(user_id).await.unwrap();
+        sleep(save_delay * 2).await;
+        assert_eq!(storage.load_all().unwrap().len(), 1);
+
+        sleep(idle_ttl).await;
+        closed.await.unwrap();
+        assert!(matches!(
+            handle.join_room("b".to_string()).await,
+            Err(Error::RoomClosed)
+        ));
+        sleep(save_delay * 2).await;
+        assert!(storage.load_all().unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bursts_of_changes_are_saved_once() {
+        let storage = Arc::new(MemoryStorage::default());
+        let options = RoomOptions {
+            storage: Some(storage.clone()),
+            ..RoomOptions::default()
+        };
+        let save_delay = options.save_delay;
+        let handle = RoomManagerHandle::new(RoomId::new(), Room::<TestGame>::new(options), || ());
+        for name in ["a", "b", "c"] {
+            handle.join_room(name.to_string()).await.unwrap();
+        }
+        sleep(save_delay / 2).await;
+        assert_eq!(storage.saves.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        sleep(save_delay).await;
+        assert_eq!(storage.saves.load(std::sync::atomic::Ordering::SeqCst), 1);
+        let (_, snapshot) = storage.load_all().unwrap().pop().unwrap();
+        let room = Room::<TestGame>::restore(snapshot, RoomOptions::default()).unwrap();
+        assert_eq!(room.user_info().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn restored_games_publish_their_clock() {
+        let mut room = Room::<TestGame>::default();
+        let (leader, token) = {
+            let data = room.join_room(JoinInfo::Username("a".to_string())).unwrap();
+            (data.id, data.token.clone())
+        };
+        let config = TestConfig {
+            bank_ms: Some(60_000),
+            ..TestConfig::default()
+        };
+        room.update_config(&leader, config).unwrap();
+        room.start_game(&leader).unwrap();
+        let room = Room::<TestGame>::restore(room.snapshot(), RoomOptions::default()).unwrap();
+
+        let handle = RoomManagerHandle::new(RoomId::new(), room, || ());
+        handle.rejoin_room(token).await.unwrap();
+        assert!(handle.watch_clock().borrow().is_some());
+    }
+
+    // A manager, not yet running, for [config]'s game between [players].
+    fn started_manager(config: TestConfig, players: &[&str]) -> RoomManager<TestGame> {
+        let (mut room, users) = room_with_users(players);
+        room.update_config(&users[0], config).unwrap();
+        room.start_game(&users[0]).unwrap();
+        RoomManagerHandle::unstarted(RoomId::new(), room).0
+    }
+
+    fn game(room_manager: &RoomManager<TestGame>) -> &TestGame {
+        room_manager.room.player_view(None).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clock_timeouts_reach_the_game() {
+        let config = TestConfig {
+            bank_ms: Some(1000),
+            ..TestConfig::default()
+        };
+        let (handle, subscriptions) = running_game(config, &["a", "b"], &[]).await;
+        sleep(Duration::from_millis(1001)).await;
+        let _ = handle.game_log().await;
+        let view = subscriptions[0].game_view.borrow().clone().unwrap().view;
+        assert_eq!(view["timeouts"], json!([PlayerId(0)]));
+        assert_eq!(view["turn"], json!(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn due_wakeups_tick_the_game() {
+        let config = TestConfig {
+            wakeup_ms: Some(0),
+            ..TestConfig::default()
+        };
+        let (handle, subscriptions) = running_game(config, &["a"], &[]).await;
+        // The manager is idle once it has handled the wakeup
+        sleep(Duration::from_millis(1)).await;
+        let _ = handle.game_log().await;
+        let view = subscriptions[0].game_view.borrow().clone().unwrap().view;
+        assert_eq!(view["ticks"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn game_wakeups_are_scheduled_as_instants() {
+        let config = TestConfig {
+            wakeup_ms: Some(60_000),
+            ..TestConfig::default()
+        };
+        let room_manager = started_manager(config, &["a"]);
+        let wakeup = room_manager.next_wakeup().unwrap();
+        // Only the wall time spent since the game started separates them
+        let expected = Instant::now() + Duration::from_secs(60);
+        assert!(wakeup <= expected && wakeup > expected - Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stale_wakeups_are_not_retried() {
+        let config = TestConfig {
+            wakeup_ms: Some(0),
+            stuck_wakeup: true,
+            ..TestConfig::default()
+        };
+        let mut room_manager = started_manager(config, &["a"]);
+        room_manager.handle_timers(Instant::now(), &mut Dirty::default());
+        assert!(room_manager.next_game_wakeup().is_none());
+        room_manager.handle_timers(Instant::now(), &mut Dirty::default());
+        assert_eq!(game(&room_manager).ticks.len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeouts_stop_once_the_game_ends() {
+        let config = TestConfig {
+            bank_ms: Some(1000),
+            simultaneous: true,
+            timeout_ends: true,
+            ..TestConfig::default()
+        };
+        let mut room_manager = started_manager(config, &["a", "b"]);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        room_manager.handle_timers(Instant::now(), &mut Dirty::default());
+        assert_eq!(game(&room_manager).timeouts.len(), 1);
+        assert!(room_manager.room.outcome().is_some());
+    }
 }
@@ -3,14 +3,19 @@ use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 
+use serde::de::DeserializeOwned;
+
 use tokio::net::{TcpListener, TcpStream};
 use tokio::runtime::Handle;
 use tokio_tungstenite::tungstenite::Result;
 use tokio_tungstenite::{accept_async, tungstenite::Error};
+use tracing::warn;
 
 use crate::client_handler::ClientHandler;
 use crate::game::Game;
 use crate::ids::*;
+use crate::options::RoomOptions;
+use crate::room::Room;
 use crate::room_manager::RoomManagerHandle;
 
 type Rooms<T> = Arc<Mutex<HashMap<RoomId, RoomManagerHandle<T>>>>;
@@ -20,8 +25,13 @@ pub struct Server<T: Game> {
 }
 
 impl<T: Game> Server<T> {
-    async fn accept_connection(peer: SocketAddr, stream: TcpStream, rooms: Rooms<T>) {
-        if let Err(e) = Self::handle_connection(peer, stream, rooms).await {
+    async fn accept_connection(
+        peer: SocketAddr,
+        stream: TcpStream,
+        rooms: Rooms<T>,
+        options: RoomOptions,
+    ) {
+        if let Err(e) = Self::handle_connection(peer, stream, rooms, options).await {
             match e {
                 Error::ConnectionClosed | Error::Protocol(_) | Error::Utf8 => (),
                 err => eprintln!("Error processing connection: {}", err),
@@ -29,20 +39,78 @@ impl<T: Game> Server<T> {
         }
     }
 
-    async fn handle_connection(peer: SocketAddr, stream: TcpStream, rooms: Rooms<T>) -> Result<()> {
+    async fn handle_connection(
+        peer: SocketAddr,
+        stream: TcpStream,
+        rooms: Rooms<T>,
+        options: RoomOptions,
+    ) -> Result<()> {
         let ws_stream = accept_async(stream).await.expect("Failed to accept");
 
         println!("New WebSocket connection: {}", peer);
 
-        let mut client = ClientHandler::new(rooms, ws_stream).await?;
-        client.run().await
+        let mut client = ClientHandler::new(rooms, options, ws_stream).await?;
+        let result = client.run().await;
+        client.disconnect().await;
+        result
+    }
+
+    // Recreates the rooms kept in [options.storage] from before a restart.
+    fn restore_rooms(rooms: &Rooms<T>, options: &RoomOptions)
+    where
+        T: DeserializeOwned,
+    {
+        let Some(storage) = &options.storage else {
+            return;
+        };
+        let snapshots = match storage.load_all() {
+            Ok(snapshots) => snapshots,
+            Err(err) => {
+                warn!("could not load rooms: {}", err);
+                return;
+            }
+        };
+        let mut rooms_map = rooms.lock().unwrap();
+        for (room_id, snapshot) in snapshots {
+            let room = match Room::<T>::restore(snapshot, options.clone()) {
+                Ok(room) => room,
+                Err(err) => {
+                    warn!("could not restore room {}: {}", room_id, err);
+                    continue;
+                }
+            };
+            let on_close = {
+                let rooms = rooms.clone();
+                let room_id = room_id.clone();
+                move || {
+                    rooms.lock().unwrap().remove(&room_id);
+                }
+            };
+            rooms_map.insert(
+                room_id.clone(),
+                RoomManagerHandle::new(room_id, room, on_close),
+            );
+        }
     }
 
     pub async fn run(addr: String) {
+        Self::serve(addr, RoomOptions::default(), Rooms::default()).await
+    }
+
+    // Serves rooms with [options]. If [options.storage] is set, the rooms kept there from before a
+    // restart are brought back first.
+    pub async fn run_with_options(addr: String, options: RoomOptions)
+    where
+        T: DeserializeOwned,
+    {
+        let rooms = Rooms::default();
+        Self::restore_rooms(&rooms, &options);
+        Self::serve(addr, options, rooms).await
+    }
+
+    async fn serve(addr: String, options: RoomOptions, rooms: Rooms<T>) {
         let handle = Handle::current();
         let listener = TcpListener::bind(addr).await.expect("Can't listen");
-        let rooms: Arc<Mutex<HashMap<RoomId, RoomManagerHandle<T>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
 
         while let Ok((stream, _)) = listener.accept().await {
             let peer = stream
@@ -50,7 +118,12 @@ impl<T: Game> Server<T> {
                 .expect("connected streams should have a peer address");
             println!("Peer address: {}", peer);
 
-            handle.spawn(Self::accept_connection(peer, stream, rooms.clone()));
+            handle.spawn(Self::accept_connection(
+                peer,
+                stream,
+                rooms.clone(),
+                options.clone(),
+            ));
         }
     }
 }
@@ -0,0 +1,244 @@
+use std::fmt::Debug;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::error::Error;
+use crate::ids::RoomId;
+use crate::result::Result;
+
+// Somewhere to keep room snapshots so that rooms survive a server restart. Writes happen on a
+// blocking thread, at most once per [RoomOptions::save_delay] for each room.
+pub trait Storage: Debug + Send + Sync + 'static {
+    fn save(&self, room_id: &RoomId, snapshot: &Value) -> Result<()>;
+    fn delete(&self, room_id: &RoomId) -> Result<()>;
+    // Entries that can't be read are skipped with a warning rather than failing the whole load.
+    fn load_all(&self) -> Result<Vec<(RoomId, Value)>>;
+}
+
+fn storage_error(err: impl ToString) -> Error {
+    Error::StorageError(err.to_string())
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredRoom {
+    room_id: RoomId,
+    snapshot: Value,
+}
+
+// Keeps one JSON file per room in a directory.
+#[derive(Debug)]
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(storage_error)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, room_id: &RoomId) -> PathBuf {
+        self.dir.join(format!("{}.json", room_id))
+    }
+}
+
+impl Storage for FileStorage {
+    fn save(&self, room_id: &RoomId, snapshot: &Value) -> Result<()> {
+        let contents = serde_json::to_vec(&StoredRoom {
+            room_id: room_id.clone(),
+            snapshot: snapshot.clone(),
+        })
+        .map_err(storage_error)?;
+        // Write to a temporary file first so that a crash never leaves a truncated snapshot
+        let path = self.path(room_id);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, contents).map_err(storage_error)?;
+        fs::rename(&tmp_path, &path).map_err(storage_error)
+    }
+
+    fn delete(&self, room_id: &RoomId) -> Result<()> {
+        match fs::remove_file(self.path(room_id)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(storage_error(err)),
+            _ => Ok(()),
+        }
+    }
+
+    fn load_all(&self) -> Result<Vec<(RoomId, Value)>> {
+        let mut rooms = Vec::new();
+        for entry in fs::read_dir(&self.dir).map_err(storage_error)? {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(err) => {
+                    warn!("could not list a stored room: {}", err);
+                    continue;
+                }
+            };
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let stored = fs::read(&path).map_err(storage_error).and_then(|contents| {
+                serde_json::from_slice::<StoredRoom>(&contents).map_err(storage_error)
+            });
+            match stored {
+                Ok(stored) => rooms.push((stored.room_id, stored.snapshot)),
+                Err(err) => warn!("skipping stored room {}: {}", path.display(), err),
+            }
+        }
+        Ok(rooms)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use rusqlite::{params, Connection};
+    use serde_json::Value;
+    use tracing::warn;
+
+    use super::{storage_error, Storage};
+    use crate::ids::RoomId;
+    use crate::result::Result;
+
+    // Keeps room snapshots in a single SQLite table.
+    #[derive(Debug)]
+    pub struct SqliteStorage {
+        connection: Mutex<Connection>,
+    }
+
+    impl SqliteStorage {
+        pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+            let connection = Connection::open(path).map_err(storage_error)?;
+            connection
+                .execute(
+                    "CREATE TABLE IF NOT EXISTS rooms (id TEXT PRIMARY KEY, snapshot TEXT NOT NULL)",
+                    [],
+                )
+                .map_err(storage_error)?;
+            Ok(Self {
+                connection: Mutex::new(connection),
+            })
+        }
+    }
+
+    impl Storage for SqliteStorage {
+        fn save(&self, room_id: &RoomId, snapshot: &Value) -> Result<()> {
+            self.connection
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT INTO rooms (id, snapshot) VALUES (?1, ?2)
+                     ON CONFLICT(id) DO UPDATE SET snapshot = excluded.snapshot",
+                    params![room_id.to_string(), snapshot.to_string()],
+                )
+                .map_err(storage_error)?;
+            Ok(())
+        }
+
+        fn delete(&self, room_id: &RoomId) -> Result<()> {
+            self.connection
+                .lock()
+                .unwrap()
+                .execute(
+                    "DELETE FROM rooms WHERE id = ?1",
+                    params![room_id.to_string()],
+                )
+                .map_err(storage_error)?;
+            Ok(())
+        }
+
+        fn load_all(&self) -> Result<Vec<(RoomId, Value)>> {
+            let connection = self.connection.lock().unwrap();
+            let mut statement = connection
+                .prepare("SELECT id, snapshot FROM rooms")
+                .map_err(storage_error)?;
+            let rows = statement
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(storage_error)?;
+            let mut rooms = Vec::new();
+            for row in rows {
+                let (room_id, snapshot) = match row {
+                    Ok(row) => row,
+                    Err(err) => {
+                        warn!("could not read a stored room: {}", err);
+                        continue;
+                    }
+                };
+                match serde_json::from_str(&snapshot) {
+                    Ok(snapshot) => rooms.push((RoomId::from(room_id), snapshot)),
+                    Err(err) => warn!("skipping stored room {}: {}", room_id, err),
+                }
+            }
+            Ok(rooms)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // Saves, overwrites and deletes rooms, expecting [storage] to start out empty.
+    fn round_trip(storage: &dyn Storage) {
+        let a = RoomId::from("AAAA".to_string());
+        let b = RoomId::from("BBBB".to_string());
+        storage.save(&a, &json!({"version": 1})).unwrap();
+        storage.save(&b, &json!({"version": 1})).unwrap();
+        storage.save(&a, &json!({"version": 2})).unwrap();
+        let mut rooms = storage.load_all().unwrap();
+        rooms.sort_by_key(|(room_id, _)| room_id.to_string());
+        assert_eq!(
+            rooms,
+            vec![
+                (a.clone(), json!({"version": 2})),
+                (b, json!({"version": 1}))
+            ]
+        );
+        storage.delete(&a).unwrap();
+        assert_eq!(storage.load_all().unwrap().len(), 1);
+        // Deleting a room that isn't stored is fine
+        storage.delete(&a).unwrap();
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("board-game-io-{}", RoomId::new()))
+    }
+
+    #[test]
+    fn file_storage_round_trip() {
+        let dir = temp_dir();
+        round_trip(&FileStorage::new(&dir).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_storage_skips_unreadable_rooms() {
+        let dir = temp_dir();
+        let storage = FileStorage::new(&dir).unwrap();
+        let room_id = RoomId::from("AAAA".to_string());
+        storage.save(&room_id, &json!(1)).unwrap();
+        fs::write(dir.join("BBBB.json"), "not json").unwrap();
+        assert_eq!(storage.load_all().unwrap(), vec![(room_id, json!(1))]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_storage_round_trip() {
+        round_trip(&SqliteStorage::new(":memory:").unwrap());
+    }
+}
@@ -0,0 +1,191 @@
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::TimeControl;
+use crate::error::Error;
+use crate::game::{Game, GameRng, Outcome};
+use crate::ids::{PlayerId, UserId};
+use crate::result::Result;
+use crate::room::{JoinInfo, Room};
+
+// A small turn-based game for tests: players take turns adding numbers to a running total. Adding
+// 0 asks for a wakeup, and nobody moves until it has happened. The player to move is offered
+// adding 1, 2 or 3 as legal actions, though any number goes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TestConfig {
+    // The game ends once the total reaches this
+    pub target: Option<i64>,
+    // Players are split round-robin into this many teams
+    pub teams: Option<u32>,
+    // Time each player has for the whole game, in milliseconds
+    pub bank_ms: Option<u64>,
+    // Wake the game up this long after it starts, in milliseconds
+    pub wakeup_ms: Option<u64>,
+    pub max_players: Option<u32>,
+    // Everyone may move at any time rather than taking turns
+    pub simultaneous: bool,
+    // The game ends as soon as anyone runs out of time
+    pub timeout_ends: bool,
+    // Ticks leave the wakeup in place, like a game that forgets to move it on
+    pub stuck_wakeup: bool,
+    // Reported by [Game::changed_views] after every change
+    pub changed_views: Option<Vec<Option<PlayerId>>>,
+    // Counts calls to [Game::view] on games created with this config
+    #[serde(skip)]
+    pub views: Arc<AtomicUsize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TestGame {
+    pub config: TestConfig,
+    pub players: Vec<PlayerId>,
+    pub total: i64,
+    pub turn: usize,
+    // A value drawn from the game's generator on every change
+    pub rolls: Vec<u32>,
+    pub wakeup: Option<SystemTime>,
+    // Times passed to [Game::tick]
+    pub ticks: Vec<SystemTime>,
+    // Players that ran out of time, in order
+    pub timeouts: Vec<PlayerId>,
+}
+
+impl Game for TestGame {
+    type View<'a> = &'a TestGame;
+    type Action = i64;
+    type Config = TestConfig;
+    type Event = i64;
+
+    const PUBLISH_LEGAL_ACTIONS: bool = true;
+
+    fn new(config: TestConfig, players: u32, rng: &mut GameRng) -> Result<Self> {
+        Ok(Self {
+            wakeup: config
+                .wakeup_ms
+                .map(|ms| SystemTime::now() + Duration::from_millis(ms)),
+            config,
+            players: (0..players).map(PlayerId).collect(),
+            total: 0,
+            turn: 0,
+            rolls: vec![rng.gen()],
+            ticks: Vec::new(),
+            timeouts: Vec::new(),
+        })
+    }
+
+    fn player_range(config: &TestConfig) -> RangeInclusive<u32> {
+        1..=config.max_players.unwrap_or(u32::MAX)
+    }
+
+    fn players(&self) -> Vec<PlayerId> {
+        self.players.clone()
+    }
+
+    fn view<'a>(&'a self, _: Option<PlayerId>) -> Self::View<'a> {
+        self.config.views.fetch_add(1, Ordering::SeqCst);
+        self
+    }
+
+    fn do_action(
+        &mut self,
+        _: PlayerId,
+        action: &i64,
+        rng: &mut GameRng,
+        events: &mut Vec<i64>,
+    ) -> Result<()> {
+        self.total += action;
+        self.turn += 1;
+        if *action == 0 {
+            self.wakeup = Some(SystemTime::now());
+        }
+        self.rolls.push(rng.gen());
+        events.push(*action);
+        Ok(())
+    }
+
+    fn validate_config(config: &TestConfig) -> Result<()> {
+        if config.teams == Some(0) {
+            return Err(Error::InvalidConfig("teams must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+
+    fn legal_actions(&self, player: PlayerId) -> Vec<i64> {
+        match self.active_players() {
+            Some(active) if active.contains(&player) => vec![1, 2, 3],
+            _ => Vec::new(),
+        }
+    }
+
+    fn active_players(&self) -> Option<Vec<PlayerId>> {
+        if self.wakeup.is_some() {
+            return Some(Vec::new());
+        }
+        if self.config.simultaneous {
+            return None;
+        }
+        Some(vec![self.players[self.turn % self.players.len()]])
+    }
+
+    fn time_control(config: &TestConfig) -> Option<TimeControl> {
+        config.bank_ms.map(|ms| TimeControl {
+            bank: Some(Duration::from_millis(ms)),
+            ..TimeControl::default()
+        })
+    }
+
+    fn on_timeout(&mut self, player: PlayerId, _: &mut GameRng, _: &mut Vec<i64>) -> Result<()> {
+        self.timeouts.push(player);
+        self.turn += 1;
+        Ok(())
+    }
+
+    fn next_wakeup(&self) -> Option<SystemTime> {
+        self.wakeup
+    }
+
+    fn tick(&mut self, now: SystemTime, rng: &mut GameRng, _: &mut Vec<i64>) -> Result<()> {
+        self.ticks.push(now);
+        self.rolls.push(rng.gen());
+        if !self.config.stuck_wakeup {
+            self.wakeup = None;
+        }
+        Ok(())
+    }
+
+    fn changed_views(&self) -> Option<Vec<Option<PlayerId>>> {
+        self.config.changed_views.clone()
+    }
+
+    fn team(&self, player: PlayerId) -> Option<u32> {
+        self.config.teams.map(|teams| player.0 % teams)
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        let reached = self
+            .config
+            .target
+            .is_some_and(|target| self.total >= target);
+        let timed_out = self.config.timeout_ends && !self.timeouts.is_empty();
+        (reached || timed_out).then(|| Outcome::draw(&self.players))
+    }
+}
+
+// A room with a user for each name, all seated in order. The first user leads.
+pub fn room_with_users(names: &[&str]) -> (Room<TestGame>, Vec<UserId>) {
+    let mut room = Room::default();
+    let users = names
+        .iter()
+        .map(|name| {
+            room.join_room(JoinInfo::Username(name.to_string()))
+                .unwrap()
+                .id
+        })
+        .collect();
+    (room, users)
+}